@@ -110,6 +110,10 @@ pub fn mul(l: RcExpr, r: RcExpr) -> RcExpr {
     RcExpr::new(Expr::Bop(BinaryOp::Mul, l, r))
 }
 
+pub fn mulhi(l: RcExpr, r: RcExpr) -> RcExpr {
+    RcExpr::new(Expr::Bop(BinaryOp::MulHi, l, r))
+}
+
 pub fn div(l: RcExpr, r: RcExpr) -> RcExpr {
     RcExpr::new(Expr::Bop(BinaryOp::Div, l, r))
 }
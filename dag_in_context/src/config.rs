@@ -1,2 +1,68 @@
 #![allow(dead_code)]
 pub(crate) const FUNCTION_INLINING_ITERATIONS: usize = 2;
+/// How many specialized clones of a function to make for constant arguments.
+pub(crate) const MAX_SPECIALIZATIONS_PER_FUNCTION: usize = 4;
+/// Functions larger than this (see `Expr-size`) are not specialized,
+/// since each clone is a full copy of the body.
+pub(crate) const MAX_SPECIALIZED_FUNCTION_SIZE: usize = 120;
+/// How many loop-carried values the cost model assumes fit in registers.
+pub(crate) const LOOP_REGISTERS: usize = 12;
+/// How many allocations the points-to analysis tracks for one pointer
+/// before it assumes the pointer points anywhere.
+pub(crate) const MAX_POINTEE_ALLOCS: usize = 8;
+/// How many rounds of the points-to analysis run in each helpers schedule.
+/// Facts from `mem-simple` are used when it doesn't finish.
+pub(crate) const POINTS_TO_ROUNDS: usize = 3;
+/// How many iterations of a loop the points-to analysis follows exactly
+/// before it forgets offsets to reach a fixed point.
+pub(crate) const POINTS_TO_ITERS_BEFORE_WIDENING: usize = 3;
+/// The largest allocation, in cells, that scalar replacement turns into values.
+pub(crate) const MAX_SCALAR_REPLACEMENT_CELLS: usize = 8;
+
+/// Controls which calls are inlined on the `InlineWithSchedule` pass.
+/// Functions that are part of a recursive strongly connected component
+/// of the call graph are never inlined.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InlinePolicy {
+    /// How many rounds of inlining to perform.
+    /// Each round inlines calls that were exposed by the previous one.
+    pub iterations: usize,
+    /// Inline any callee whose tree size (see `Expr-size`) is at most this.
+    pub max_callee_size: usize,
+    /// Inline callees that make no calls themselves when their size is at most this.
+    pub max_leaf_size: usize,
+    /// Inline callees with exactly one call site regardless of their size,
+    /// since this doesn't grow the program.
+    pub inline_single_call_site: bool,
+}
+
+impl InlinePolicy {
+    /// Never inline anything.
+    pub fn none() -> Self {
+        InlinePolicy {
+            iterations: 0,
+            ..InlinePolicy::default()
+        }
+    }
+
+    /// Inline every non-recursive callee, regardless of size.
+    pub fn all() -> Self {
+        InlinePolicy {
+            iterations: FUNCTION_INLINING_ITERATIONS,
+            max_callee_size: usize::MAX,
+            max_leaf_size: usize::MAX,
+            inline_single_call_site: true,
+        }
+    }
+}
+
+impl Default for InlinePolicy {
+    fn default() -> Self {
+        InlinePolicy {
+            iterations: FUNCTION_INLINING_ITERATIONS,
+            max_callee_size: 40,
+            max_leaf_size: 120,
+            inline_single_call_site: true,
+        }
+    }
+}
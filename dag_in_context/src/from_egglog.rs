@@ -172,6 +172,7 @@ impl<'a> FromEgglog<'a> {
           ("Add", []) => BinaryOp::Add,
           ("Sub", []) => BinaryOp::Sub,
           ("Mul", []) => BinaryOp::Mul,
+          ("MulHi", []) => BinaryOp::MulHi,
           ("Div", []) => BinaryOp::Div,
           ("Eq", []) => BinaryOp::Eq,
           ("LessThan", []) => BinaryOp::LessThan,
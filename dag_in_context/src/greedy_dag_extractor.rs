@@ -12,8 +12,10 @@ use std::{
 use strum::IntoEnumIterator;
 
 use crate::{
+    config::LOOP_REGISTERS,
     from_egglog::FromEgglog,
-    linearity::check_function_is_linear,
+    linearity::check_function_is_linear_with_effects,
+    optimizations::effect_summary::{function_effects, Effect},
     schema::{Expr, RcExpr, TreeProgram, Type},
     schema_helpers::Sort,
     typechecker::TypeChecker,
@@ -32,6 +34,9 @@ pub(crate) struct EgraphInfo<'a> {
     /// Optionally, a loop with (inputs, outputs) can have an estimated number of iterations.
     /// This is found by looking at LoopNumItersGuess in the database.
     pub(crate) loop_iteration_estimates: IndexMap<(RootId, RootId), i64>,
+    /// The number of accesses a loop makes each iteration that don't have unit stride,
+    /// found by looking at LoopStridedAccesses in the database.
+    pub(crate) loop_strided_accesses: IndexMap<(RootId, RootId), i64>,
     /// A set of names of functions that are unextractable
     unextractables: IndexSet<String>,
 }
@@ -74,7 +79,9 @@ impl<'a> EgraphInfo<'a> {
         self.egraph.nid_to_cid(nid).clone()
     }
 
-    fn get_loop_iteration_estimates(egraph: &EGraph) -> IndexMap<(ClassId, ClassId), i64> {
+    /// Finds the value of the loop function `op` (like `LoopNumItersGuess`)
+    /// for every loop's (inputs, outputs).
+    fn get_loop_facts(egraph: &EGraph, op: &str) -> IndexMap<(ClassId, ClassId), i64> {
         // for every eclass that represents a single i64 in the egraph,
         // map the eclass to that integer
         let mut integers: IndexMap<ClassId, i64> = IndexMap::default();
@@ -85,19 +92,19 @@ impl<'a> EgraphInfo<'a> {
             }
         }
 
-        let mut loop_iteration_estimates = IndexMap::default();
+        let mut loop_facts = IndexMap::default();
 
-        // loop over all nodes, finding LoopNumItersGuess nodes
+        // loop over all nodes, finding `op` nodes
         for (_nodeid, node) in &egraph.nodes {
-            if node.op == "LoopNumItersGuess" {
+            if node.op == op {
                 // assert it has two children
                 assert_eq!(
                     node.children.len(),
                     2,
-                    "LoopNumItersGuess node has wrong number of children. Node: {:?}",
+                    "{op} node has wrong number of children. Node: {:?}",
                     node
                 );
-                loop_iteration_estimates.insert(
+                loop_facts.insert(
                     (
                         egraph.nid_to_cid(&node.children[0]).clone(),
                         egraph.nid_to_cid(&node.children[1]).clone(),
@@ -106,7 +113,7 @@ impl<'a> EgraphInfo<'a> {
                 );
             }
         }
-        loop_iteration_estimates
+        loop_facts
     }
 
     pub(crate) fn new(
@@ -116,7 +123,8 @@ impl<'a> EgraphInfo<'a> {
         egraph: &'a EGraph,
         unextractables: IndexSet<String>,
     ) -> Self {
-        let loop_iteration_estimates = Self::get_loop_iteration_estimates(egraph);
+        let loop_iteration_estimates = Self::get_loop_facts(egraph, "LoopNumItersGuess");
+        let loop_strided_accesses = Self::get_loop_facts(egraph, "LoopStridedAccesses");
 
         // get all the roots needed
         let mut region_roots = find_reachable(egraph, func_root.clone(), cm, false, true);
@@ -209,6 +217,7 @@ impl<'a> EgraphInfo<'a> {
             parents: parents_sorted,
             roots,
             loop_iteration_estimates,
+            loop_strided_accesses,
         }
     }
 }
@@ -494,25 +503,80 @@ impl<'a> Extractor<'a> {
                 .unwrap_or(1000);
 
             let child_type = self.typecheck_term(&child_set.term);
+            let carried_type = self.loop_carried_type(&child_set.term, child_type);
             // register pressure- add the cost of keeping lots of registers around, one per value passing through loop
             let register_pressure = info
                 .cm
-                .loop_register_pressure_cost(&child_type, loop_num_iters_guess);
+                .loop_register_pressure_cost(&carried_type, loop_num_iters_guess);
 
-            child_set.total * NotNan::new(loop_num_iters_guess as f64).unwrap() + register_pressure
+            // accesses without unit stride miss the cache more often
+            let strided = info
+                .loop_strided_accesses
+                .get(&(inputs.clone(), outputs.clone()))
+                .cloned()
+                .unwrap_or(0);
+            let locality = info
+                .cm
+                .loop_strided_access_cost(strided, loop_num_iters_guess);
+
+            child_set.total * NotNan::new(loop_num_iters_guess as f64).unwrap()
+                + register_pressure
+                + locality
         } else if node.op == "If" {
-            // Currently we don't do this for "Switch"
-            // because the branches of Switch is hidden
-            // behind an ListExpr
             assert!(child_set.len() == 2);
             let thn = child_set[0];
             let els = child_set[1];
             max(thn.total, els.total) + min(thn.total, els.total) * 0.3
+        } else if node.op == "Switch" {
+            // Like an If, the most expensive branch counts fully.
+            // The other branches share the weight an If gives its cheaper branch,
+            // since only one of them runs.
+            // Each branch past the second needs one more comparison when lowered,
+            // but a switch assigns its outputs once, where a chain of Ifs
+            // pays for a region at every level.
+            let mut totals: Vec<Cost> = child_set.iter().map(|cs| cs.total).collect();
+            totals.sort();
+            let most = totals.pop().expect("switch with no branches");
+            let rest = if totals.is_empty() {
+                NotNan::new(0.).unwrap()
+            } else {
+                totals.iter().copied().sum::<Cost>() * 0.3 / totals.len() as f64
+            };
+            let dispatch = info.cm.get_op_cost("Eq") * totals.len().saturating_sub(1) as f64;
+            most + rest + dispatch
         } else {
             child_set.iter().map(|cs| cs.total).sum()
         }
     }
 
+    /// The types of the values a loop body computes, including the predicate.
+    /// Values passed through unchanged don't need to be moved between iterations,
+    /// so they are left out.
+    fn loop_carried_type(&self, body: &Term, body_type: Type) -> Type {
+        let (Some(outputs), Type::TupleT(tys)) = (self.try_break_up_term(body), &body_type) else {
+            return body_type;
+        };
+        Type::TupleT(
+            outputs
+                .iter()
+                .zip(tys)
+                .enumerate()
+                .filter(|(i, (output, _))| *i == 0 || !self.is_arg_get(output, i - 1))
+                .map(|(_, (_, ty))| ty.clone())
+                .collect(),
+        )
+    }
+
+    /// Checks if `term` is `(Get (Arg ty ctx) index)`.
+    fn is_arg_get(&self, term: &Term, index: usize) -> bool {
+        let Term::App(head, children) = term else {
+            return false;
+        };
+        head.to_string() == "Get"
+            && matches!(self.termdag.get(children[0]), Term::App(arg, _) if arg.to_string() == "Arg")
+            && matches!(self.termdag.get(children[1]), Term::Lit(Literal::Int(i)) if *i == index as i64)
+    }
+
     fn try_break_up_term(&self, term: &Term) -> Option<Vec<Term>> {
         match term {
             Term::App(head, children) => {
@@ -820,6 +884,7 @@ fn extract_fn(
     termdag: &mut TermDag,
     cost_model: &impl CostModel,
     should_maintain_linearity: bool,
+    effects: &IndexMap<String, Effect>,
 ) -> (CostSet, RcExpr) {
     // prune egraph
     let egraph = prune_egraph(&egraph, rootid.clone(), cost_model);
@@ -850,7 +915,7 @@ fn extract_fn(
             &egraph_info,
             Some(&effectful_nodes_along_path),
         );
-        check_function_is_linear(&res, original_prog).unwrap();
+        check_function_is_linear_with_effects(&res, original_prog, effects).unwrap();
 
         (cost_res, res)
     }
@@ -886,6 +951,8 @@ pub fn greedy_dag_extract(
     should_maintain_linearity: bool,
     extract_debug_exprs: bool,
 ) -> (Cost, TreeProgram) {
+    // extraction doesn't change what functions do, so compute this once
+    let effects = function_effects(original_prog);
     let (cost, mut prog) = if extract_debug_exprs {
         log::info!("Extracting debug expressions.");
         let debug_roots = find_debug_roots(egraph.clone());
@@ -902,6 +969,7 @@ pub fn greedy_dag_extract(
                 termdag,
                 &cost_model,
                 false,
+                &effects,
             );
             total_cost += cost.total;
             let output_ty = typechecker
@@ -931,6 +999,7 @@ pub fn greedy_dag_extract(
                 termdag,
                 &cost_model,
                 should_maintain_linearity,
+                &effects,
             );
             new_prog.replace_fn(&func, extracted);
             cost += fn_cost.total;
@@ -1073,12 +1142,21 @@ pub trait CostModel {
     fn ignore_children(&self, op: &str) -> bool;
 
     fn loop_register_pressure_cost(&self, ty: &Type, loop_num_iters_guess: i64) -> Cost {
-        match ty {
-            Type::TupleT(tys) => {
-                NotNan::new(tys.len() as f64 * loop_num_iters_guess as f64 * 35.0).unwrap()
-            }
-            _ => NotNan::new(1.0 * loop_num_iters_guess as f64 * 35.0).unwrap(),
-        }
+        let num_values = match ty {
+            Type::TupleT(tys) => tys.len(),
+            _ => 1,
+        };
+        // values that don't fit in registers are spilled and reloaded every iteration
+        let spilled = num_values.saturating_sub(LOOP_REGISTERS);
+        NotNan::new(
+            (num_values as f64 * 35.0 + spilled as f64 * 100.0) * loop_num_iters_guess as f64,
+        )
+        .unwrap()
+    }
+
+    fn loop_strided_access_cost(&self, num_strided: i64, loop_num_iters_guess: i64) -> Cost {
+        // roughly a cache miss for each strided access on every iteration
+        NotNan::new((num_strided * loop_num_iters_guess) as f64 * 100.0).unwrap()
     }
 }
 
@@ -1127,6 +1205,8 @@ impl CostModel for DefaultCostModel {
             | "Shr" => 10.,
             "FAdd" | "FSub" | "Fmax" | "Fmin" => 50.,
             "Mul" => 30.,
+            // a single widening multiply in the LLVM backend, like Mul
+            "MulHi" => 30.,
             "FMul" => 150.,
             "Div" => 50.,
             "FDiv" => 250.,
@@ -1518,15 +1598,17 @@ fn dag_extraction_test(prog: &TreeProgram, expected_cost: NotNan<f64>) {
     );
 }
 
-/// This only runs extract_without_linearity once
-/// and check if the extracted program violates linearity.
+/// Runs `schedule` on the program, then extracts each function once
+/// without enforcing linearity and checks if the result is linear.
+/// `schedule` may also contain egglog checks.
+/// Returns the extracted program.
 #[cfg(test)]
-fn dag_extraction_linearity_check(prog: &TreeProgram, error_message: &str) {
+fn extract_without_linearity(prog: &TreeProgram, schedule: &str) -> Result<TreeProgram, String> {
     use crate::{print_with_intermediate_vars, prologue};
     let string_prog = {
         let (term, termdag) = prog.to_egglog();
         let printed = print_with_intermediate_vars(&termdag, term);
-        format!("{}\n{printed}\n", prologue(),)
+        format!("{}\n{printed}\n{schedule}\n", prologue(),)
     };
 
     let mut egraph = egglog::EGraph::default();
@@ -1534,7 +1616,7 @@ fn dag_extraction_linearity_check(prog: &TreeProgram, error_message: &str) {
     let (serialized_egraph, unextractables) = serialized_egraph(egraph);
     let mut termdag = TermDag::default();
 
-    let mut err = Ok(());
+    let mut res = prog.clone();
     for func in prog.fns() {
         let root = get_root(&serialized_egraph, &func);
         let pruned = prune_egraph(&serialized_egraph, root.clone(), &DefaultCostModel);
@@ -1548,20 +1630,24 @@ fn dag_extraction_linearity_check(prog: &TreeProgram, error_message: &str) {
         );
         let extractor_not_linear = &mut Extractor::new(prog, &mut termdag);
 
-        let (_cost_res, func) = extract_with_paths(
+        let (_cost_res, extracted) = extract_with_paths(
             &func,
             root.clone(),
             extractor_not_linear,
             &egraph_info,
             None,
         );
-        let res = check_function_is_linear(&func, prog);
-        if let Err(e) = res {
-            err = Err(e);
-            break;
-        }
+        crate::linearity::check_function_is_linear(&extracted, prog)?;
+        res.replace_fn(&func, extracted);
     }
-    match err {
+    Ok(res)
+}
+
+/// This only runs extract_without_linearity once
+/// and check if the extracted program violates linearity.
+#[cfg(test)]
+fn dag_extraction_linearity_check(prog: &TreeProgram, error_message: &str) {
+    match extract_without_linearity(prog, "") {
         Ok(_) => panic!("Expected program to be non-linear!"),
         Err(e) => {
             if !e.starts_with(error_message) {
@@ -1671,6 +1757,37 @@ fn test_dag_extract_if() {
     dag_extraction_test(&prog, cost_total);
 }
 
+#[test]
+fn test_dag_extract_switch() {
+    use crate::ast::*;
+    let prog = program!(function(
+        "func_switch",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(
+            get(
+                switch!(
+                    getat(0),
+                    parallel!(getat(0));
+                    parallel!(mul(getat(0), getat(0))),
+                    parallel!(add(getat(0), getat(0))),
+                    parallel!(getat(0))
+                ),
+                0
+            ),
+            getat(1)
+        )
+    ),);
+    let cost_model = TestCostModel;
+    // the multiply counts fully, the other two branches share 0.3
+    // and the third branch needs one comparison
+    let cost_switch = cost_model.get_op_cost("Mul")
+        + cost_model.get_op_cost("Add") * 0.3 / 2.
+        + cost_model.get_op_cost("Eq")
+        + cost_model.get_op_cost("Switch");
+    dag_extraction_test(&prog, cost_switch);
+}
+
 #[test]
 fn test_cost_dead_code_to_if() {
     use crate::ast::*;
@@ -1764,6 +1881,49 @@ fn test_linearity_check_2() {
     );
 }
 
+#[test]
+fn test_loop_peeling_is_weakly_linear() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    use crate::schedule::helpers;
+
+    // runs 3 times, printing the counter each time
+    let looped = dowhile(
+        parallel!(int(0), getat(1)),
+        parallel!(
+            less_than(add(getat(0), int(1)), int(3)),
+            add(getat(0), int(1)),
+            tprint(getat(0), getat(1))
+        ),
+    );
+    let prog = program!(function(
+        "main",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(get(looped.clone(), 0), get(looped, 1))
+    ),);
+    // peeling repeatedly must not create separate copies
+    // of the first iteration that share the input state
+    let helpers = helpers();
+    let schedule = format!(
+        "
+(run-schedule
+  {helpers}
+  loop-peel
+  {helpers}
+  loop-peel
+  {helpers}
+  loop-peel
+  {helpers})
+(check (LoopPeeled inputs outputs))"
+    );
+    let extracted = extract_without_linearity(&prog, &schedule).unwrap();
+    assert_eq!(
+        interpret_dag_prog(&extracted, &tuplev!(intv(0), statev())),
+        interpret_dag_prog(&prog, &tuplev!(intv(0), statev()))
+    );
+}
+
 ///                                                    
 ///         val1  state1     val2  state2              
 ///           │       │        │      │                
@@ -1861,7 +2021,7 @@ fn test_validity_of_extraction() {
         None,
     );
     // first extraction should fail linearity check
-    assert!(check_function_is_linear(&res, &prog).is_err());
+    assert!(crate::linearity::check_function_is_linear(&res, &prog).is_err());
 
     // second extraction should succeed
     greedy_dag_extract(
@@ -1989,9 +2149,9 @@ pub fn prune_egraph(
         }
     }
 
-    // copy over "LoopNumItersGuess" nodes, which depend on integers and strings
+    // copy over loop facts like "LoopNumItersGuess", which depend on integers and strings
     for (nodeid, node) in &egraph.nodes {
-        if node.op == "LoopNumItersGuess"
+        if (node.op == "LoopNumItersGuess" || node.op == "LoopStridedAccesses")
             && visited.contains(egraph.nid_to_cid(&node.children[0]))
             && visited.contains(egraph.nid_to_cid(&node.children[1]))
         {
@@ -3,13 +3,13 @@
 //! The interpreter relies on the invariant that common subexpressions are
 //! shared as the same Rc pointer. Otherwise, effects may be executed multiple times.
 //! The invariant is maintained by translation from RVSDG, type checking, and translation from egglog.
+//!
+//! Integers are 64-bit two's complement and wrap on overflow, as in Bril.
+//! This includes `i64::MIN / -1` and negating or taking the absolute value of `i64::MIN`.
+//! Shift amounts are taken modulo 64.
+//! Float comparisons follow IEEE 754, so any comparison with NaN is false.
 
-use std::{
-    collections::HashMap,
-    fmt::Display,
-    ops::{Shl, Shr},
-    rc::Rc,
-};
+use std::{collections::HashMap, fmt::Display, rc::Rc};
 
 use crate::{
     schema::{BinaryOp, Constant, Expr, RcExpr, TernaryOp, TreeProgram, UnaryOp},
@@ -245,6 +245,9 @@ impl<'a> VirtualMachine<'a> {
             BinaryOp::Mul => Const(Constant::Int(
                 get_int(e1, self).wrapping_mul(get_int(e2, self)),
             )),
+            BinaryOp::MulHi => Const(Constant::Int(
+                ((get_int(e1, self) as i128 * get_int(e2, self) as i128) >> 64) as i64,
+            )),
             BinaryOp::Div => Const(Constant::Int(
                 get_int(e1, self).wrapping_div(get_int(e2, self)),
             )),
@@ -258,8 +261,12 @@ impl<'a> VirtualMachine<'a> {
                 let b = get_int(e2, self);
                 Const(Constant::Int(if a < b { a } else { b }))
             }
-            BinaryOp::Shl => Const(Constant::Int(get_int(e1, self).shl(get_int(e2, self)))),
-            BinaryOp::Shr => Const(Constant::Int(get_int(e1, self).shr(get_int(e2, self)))),
+            BinaryOp::Shl => Const(Constant::Int(
+                get_int(e1, self).wrapping_shl(get_int(e2, self) as u32),
+            )),
+            BinaryOp::Shr => Const(Constant::Int(
+                get_int(e1, self).wrapping_shr(get_int(e2, self) as u32),
+            )),
             BinaryOp::Eq => Const(Constant::Bool(get_int(e1, self) == get_int(e2, self))),
             BinaryOp::LessThan => Const(Constant::Bool(get_int(e1, self) < get_int(e2, self))),
             BinaryOp::GreaterThan => Const(Constant::Bool(get_int(e1, self) > get_int(e2, self))),
@@ -312,15 +319,21 @@ impl<'a> VirtualMachine<'a> {
             BinaryOp::FSub => Const(Constant::Float(get_float(e1, self) - get_float(e2, self))),
             BinaryOp::FMul => Const(Constant::Float(get_float(e1, self) * (get_float(e2, self)))),
             BinaryOp::FDiv => Const(Constant::Float(get_float(e1, self) / (get_float(e2, self)))),
-            BinaryOp::FEq => Const(Constant::Bool(get_float(e1, self) == get_float(e2, self))),
-            BinaryOp::FLessThan => Const(Constant::Bool(get_float(e1, self) < get_float(e2, self))),
-            BinaryOp::FGreaterThan => {
-                Const(Constant::Bool(get_float(e1, self) > get_float(e2, self)))
-            }
-            BinaryOp::FLessEq => Const(Constant::Bool(get_float(e1, self) <= get_float(e2, self))),
-            BinaryOp::FGreaterEq => {
-                Const(Constant::Bool(get_float(e1, self) >= get_float(e2, self)))
-            }
+            BinaryOp::FEq => Const(Constant::Bool(
+                get_float(e1, self).0 == get_float(e2, self).0,
+            )),
+            BinaryOp::FLessThan => Const(Constant::Bool(
+                get_float(e1, self).0 < get_float(e2, self).0,
+            )),
+            BinaryOp::FGreaterThan => Const(Constant::Bool(
+                get_float(e1, self).0 > get_float(e2, self).0,
+            )),
+            BinaryOp::FLessEq => Const(Constant::Bool(
+                get_float(e1, self).0 <= get_float(e2, self).0,
+            )),
+            BinaryOp::FGreaterEq => Const(Constant::Bool(
+                get_float(e1, self).0 >= get_float(e2, self).0,
+            )),
             BinaryOp::Fmax => {
                 let a = get_float(e1, self);
                 let b = get_float(e2, self);
@@ -337,9 +350,9 @@ impl<'a> VirtualMachine<'a> {
     fn interpret_uop(&mut self, uop: &UnaryOp, e: &RcExpr, arg: &Value) -> Value {
         let get_int = |e: &RcExpr, vm: &mut Self| vm.interp_int_expr(e, arg);
         match uop {
-            UnaryOp::Neg => Const(Constant::Int(-self.interp_int_expr(e, arg))),
+            UnaryOp::Neg => Const(Constant::Int(get_int(e, self).wrapping_neg())),
             UnaryOp::Not => Const(Constant::Bool(!self.interp_bool_expr(e, arg))),
-            UnaryOp::Abs => Const(Constant::Int(get_int(e, self).abs())),
+            UnaryOp::Abs => Const(Constant::Int(get_int(e, self).wrapping_abs())),
         }
     }
 
@@ -533,3 +546,31 @@ fn test_interpreter() {
 
 #[test]
 fn test_recursive_interp() {}
+
+#[test]
+fn test_interpret_wrapping() {
+    use crate::ast::*;
+    let min = sub(int(-i64::MAX), int(1));
+    let expr = parallel!(
+        add(int(i64::MAX), int(1)),
+        mul(int(i64::MAX), int(2)),
+        div(min.clone(), int(-1)),
+        neg(min.clone()),
+        abs(min),
+        shl(int(1), int(64)),
+        shr(int(-8), int(65))
+    );
+    let res = interpret_expr(&expr, &emptyv());
+    assert_eq!(
+        res.value,
+        tuplev!(
+            intv(i64::MIN),
+            intv(-2),
+            intv(i64::MIN),
+            intv(i64::MIN),
+            intv(i64::MIN),
+            intv(1),
+            intv(-4)
+        )
+    );
+}
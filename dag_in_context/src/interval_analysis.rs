@@ -182,12 +182,16 @@ fn nested_if() -> crate::Result {
 
 #[test]
 fn context_if() -> crate::Result {
+    // -100 < input
+    let bounded = less_than(int_ty(-100, base(intt())), iarg());
     // input <= 0
-    let cond = less_eq(iarg(), int_ty(0, base(intt())));
+    let cond = less_eq(getat(0), int(0));
 
-    // y = if cond {-1 * input} else {input}
+    // y = if bounded { if cond {-1 * input} else {input} } else {0}
     // interval analysis should tell us that y is always positive (= (lo-bound y) (IntB 0))
-    let y = tif(cond, parallel!(arg()), mul(getat(0), int(-1)), getat(0));
+    // (without the lower bound, -1 * input overflows when input is i64::MIN)
+    let negated = tif(cond, parallel!(getat(0)), mul(getat(0), int(-1)), getat(0));
+    let y = tif(bounded, parallel!(arg()), negated, int(0));
 
     // z = y < 0
     // interval analysis should tell us that z is false
@@ -325,3 +329,245 @@ fn context_if_with_state() -> crate::Result {
         vec!["true".to_string()],
     )
 }
+
+#[test]
+fn wrapping_add_interval() -> crate::Result {
+    // y = if input < 0 {0} else {input}
+    // y is at least 0, but y + 1 wraps around when y is i64::MAX,
+    // so (y + 1) < 1 must not be folded to false
+    let y = tif(
+        less_than(iarg(), int_ty(0, base(intt()))),
+        parallel!(arg()),
+        int(0),
+        getat(0),
+    );
+    let z = less_than(add(y, int_ty(1, base(intt()))), int_ty(1, base(intt())));
+
+    let f = function("main", base(intt()), base(boolt()), z.clone()).func_with_arg_types();
+    let prog = f.to_program(base(intt()), base(boolt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!("(fail (check (= {term} (Const (Bool false) (Base (IntT)) somectx))))"),
+        vec![with_context],
+        intv(i64::MAX),
+        val_bool(true),
+        vec![],
+    )
+}
+
+#[cfg(test)]
+fn floatv(f: f64) -> Value {
+    Value::Const(Constant::Float(ordered_float::OrderedFloat(f)))
+}
+
+#[test]
+fn float_if_interval() -> crate::Result {
+    // y = if input < 4.0 { if 1.0 < input {input + 1.0} else {2.5} } else {3.0}
+    // y is between 2.0 and 5.0, so y < 2.0 is false
+    let inner = tif(
+        fless_than(float(1.0), getat(0)),
+        parallel!(getat(0)),
+        fadd(getat(0), float(1.0)),
+        float(2.5),
+    );
+    let y = tif(
+        fless_than(arg(), float(4.0)),
+        parallel!(arg()),
+        inner,
+        float(3.0),
+    );
+    let z = fless_than(y, float(2.0));
+
+    let f = function("main", base(floatt()), base(boolt()), z).func_with_arg_types();
+    let prog = f.to_program(base(floatt()), base(boolt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!("(check (= {term} (Const (Bool false) (Base (FloatT)) somectx)))"),
+        vec![with_context],
+        floatv(3.0),
+        val_bool(false),
+        vec![],
+    )
+}
+
+#[test]
+fn float_nan_comparison() -> crate::Result {
+    // y = if input < 0.0 {0.0} else {input}
+    // y is at least 0.0 unless it is NaN,
+    // so 0.0 <= y must not be folded to true
+    let y = tif(
+        fless_than(arg(), float(0.0)),
+        parallel!(arg()),
+        float(0.0),
+        getat(0),
+    );
+    let z = fless_eq(float(0.0), y);
+
+    let f = function("main", base(floatt()), base(boolt()), z).func_with_arg_types();
+    let prog = f.to_program(base(floatt()), base(boolt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!("(fail (check (= {term} (Const (Bool true) (Base (FloatT)) somectx))))"),
+        vec![with_context],
+        floatv(f64::NAN),
+        val_bool(false),
+        vec![],
+    )
+}
+
+#[test]
+fn fmax_sign_rewrite() -> crate::Result {
+    // if 0.5 < input { fmax(input, 0.0) } else { 0.0 }
+    // in the then branch, input is positive, so fmax(input, 0.0) is input
+    let body = tif(
+        fless_than(float(0.5), arg()),
+        parallel!(arg()),
+        fmax(getat(0), float(0.0)),
+        float(0.0),
+    );
+
+    let f = function("main", base(floatt()), base(floatt()), body).func_with_arg_types();
+    let prog = f.to_program(base(floatt()), base(floatt()));
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "(check (= (Bop (Fmax) x (Const (Float 0.0) ty ctx)) x))",
+        vec![with_context],
+        floatv(2.0),
+        floatv(2.0),
+        vec![],
+    )
+}
+
+#[test]
+fn loop_counter_interval() -> crate::Result {
+    // i = 0; do { i = i + 1 } while (i < 10)
+    // the trip count bounds i by 10 after the loop, so i < 11 is true
+    let next = add(getat(0), int(1));
+    let lp = dowhile(
+        single(int(0)),
+        parallel!(less_than(next.clone(), int(10)), next),
+    );
+    let z = less_than(get(lp, 0), int(11));
+
+    let f = function("main", base(intt()), base(boolt()), z).func_with_arg_types();
+    let prog = f.to_program(base(intt()), base(boolt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!("(check (= {term} (Const (Bool true) (Base (IntT)) somectx)))"),
+        vec![with_context],
+        intv(0),
+        val_bool(true),
+        vec![],
+    )
+}
+
+#[test]
+fn loop_carried_interval() -> crate::Result {
+    // i = 0; x = 5
+    // do { x = if i < 3 {8} else {x}; i = i + 1 } while (i < 10)
+    // x never drops below its initial value, so x < 5 is false
+    let next = add(getat(0), int(1));
+    let x = get(
+        tif(
+            less_than(getat(0), int(3)),
+            parallel!(getat(1)),
+            single(int(8)),
+            single(getat(0)),
+        ),
+        0,
+    );
+    let lp = dowhile(
+        parallel!(int(0), int(5)),
+        parallel!(less_than(next.clone(), int(10)), next, x),
+    );
+    let z = less_than(get(lp, 1), int(5));
+
+    let f = function("main", base(intt()), base(boolt()), z).func_with_arg_types();
+    let prog = f.to_program(base(intt()), base(boolt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!("(check (= {term} (Const (Bool false) (Base (IntT)) somectx)))"),
+        vec![with_context],
+        intv(0),
+        val_bool(false),
+        vec![],
+    )
+}
+
+#[test]
+fn nested_check_folds() -> crate::Result {
+    // if i < n { if i < n {1} else {2} } else {3}
+    // the inner check is the outer predicate, so it is true
+    let ty = tuplet!(intt(), intt());
+    let inner = tif(
+        less_than(getat(0), getat(1)),
+        parallel!(getat(0), getat(1)),
+        single(int(1)),
+        single(int(2)),
+    );
+    let outer = get(
+        tif(
+            less_than(getat(0), getat(1)),
+            parallel!(getat(0), getat(1)),
+            inner,
+            single(int(3)),
+        ),
+        0,
+    );
+
+    let f = function("main", ty.clone(), base(intt()), outer).func_with_arg_types();
+    let prog = f.to_program(ty, base(intt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!("(check (= {term} (Get (If p ins (Single (Const (Int 1) t c)) els) 0)))"),
+        vec![with_context],
+        tuplev!(intv(2), intv(5)),
+        intv(1),
+        vec![],
+    )
+}
+
+#[test]
+fn switch_case_interval() -> crate::Result {
+    // switch x { 0 => 7, 1 => x + 10 }
+    // in the second branch x is 1
+    let ty = tuplet!(intt());
+    let body = get(
+        switch!(getat(0), parallel!(getat(0)); single(int(7)), single(add(getat(0), int(10)))),
+        0,
+    );
+
+    let f = function("main", ty.clone(), base(intt()), body).func_with_arg_types();
+    let prog = f.to_program(ty, base(intt()));
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "(check (= (IntB 1) (lo-bound (Get (Arg t (InSwitch 1 p ins)) 0))))
+(check (= (IntB 1) (hi-bound (Get (Arg t (InSwitch 1 p ins)) 0))))",
+        vec![with_context],
+        tuplev!(intv(1)),
+        intv(11),
+        vec![],
+    )
+}
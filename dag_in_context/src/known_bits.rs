@@ -0,0 +1,67 @@
+#[cfg(test)]
+use crate::{ast::*, egglog_test, schema::*};
+
+#[cfg(test)]
+fn known_bits_test(inp: RcExpr, expected: RcExpr, arg: i64, expected_val: i64) -> crate::Result {
+    let ty = |e: RcExpr| e.with_arg_types(base(intt()), base(intt()));
+    let (inp, expected) = (ty(inp), ty(expected));
+    egglog_test(
+        &format!("(let inp {inp})"),
+        &format!("(check (= inp {expected}))"),
+        vec![inp.to_program(base(intt()), base(intt()))],
+        intv(arg),
+        intv(expected_val),
+        vec![],
+    )
+}
+
+#[test]
+fn redundant_mask() -> crate::Result {
+    // (x & 255) & 1023 => x & 255
+    let masked = bitand(arg(), int(255));
+    known_bits_test(bitand(masked.clone(), int(1023)), masked, 1000, 1000 & 255)
+}
+
+#[test]
+fn shifted_mask() -> crate::Result {
+    // (x << 4) & -16 => x << 4
+    let shifted = shl(arg(), int(4));
+    known_bits_test(bitand(shifted.clone(), int(-16)), shifted, -3, -48)
+}
+
+#[test]
+fn div_to_shr() -> crate::Result {
+    // (x & 255) / 4 => (x & 255) >> 2, since x & 255 is non-negative
+    let masked = bitand(arg(), int(255));
+    known_bits_test(div(masked.clone(), int(4)), shr(masked, int(2)), -1, 63)
+}
+
+#[test]
+fn no_div_to_shr_on_negative() -> crate::Result {
+    // x / 4 rounds towards zero, but x >> 2 rounds down
+    let ty = |e: RcExpr| e.with_arg_types(base(intt()), base(intt()));
+    let inp = ty(div(arg(), int(4)));
+    egglog_test(
+        &format!("(let inp {inp})"),
+        &format!("(fail (check (= inp {})))", ty(shr(arg(), int(2)))),
+        vec![inp.to_program(base(intt()), base(intt()))],
+        intv(-5),
+        intv(-1),
+        vec![],
+    )
+}
+
+#[test]
+fn known_bits_eq() -> crate::Result {
+    // (x << 1) == 7 is false, since the low bit of x << 1 is zero
+    let ty = |e: RcExpr| e.with_arg_types(base(intt()), base(boolt()));
+    let inp = ty(eq(shl(arg(), int(1)), int(7)));
+    egglog_test(
+        &format!("(let inp {inp})"),
+        "(check (= (hi-bound inp) (BoolB false)))",
+        vec![inp.to_program(base(intt()), base(boolt()))],
+        intv(3),
+        val_bool(false),
+        vec![],
+    )
+}
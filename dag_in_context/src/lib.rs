@@ -24,20 +24,30 @@ use to_egglog::TreeToEgglog;
 use crate::from_egglog::FromEgglog;
 use crate::util::{run_cmd_line_with_memory_limit, MemoryLimitExceeded};
 use crate::{
-    dag2svg::tree_to_svg, interpreter::interpret_dag_prog,
-    optimizations::function_inlining::perform_inlining, remove_context::remove_new_contexts,
+    dag2svg::tree_to_svg,
+    interpreter::interpret_dag_prog,
+    optimizations::div_by_const::div_magic_facts,
+    optimizations::effect_summary::effect_facts,
+    optimizations::function_inlining::perform_inlining,
+    optimizations::loop_interchange::interchange_unions,
+    optimizations::scalar_replacement::scalar_replace_allocations,
+    optimizations::specialize::{arg_bound_facts, specialize_functions},
+    optimizations::tail_recursion::tail_recursion_to_loops,
+    remove_context::remove_new_contexts,
     schedule::parallel_schedule,
 };
 
 pub mod add_context;
 pub mod ast;
 mod config;
+pub use config::InlinePolicy;
 pub mod dag2svg;
 pub mod dag_typechecker;
 pub mod from_egglog;
 mod greedy_dag_extractor;
 pub mod interpreter;
 pub(crate) mod interval_analysis;
+pub(crate) mod known_bits;
 mod linearity;
 mod optimizations;
 mod remove_context;
@@ -67,32 +77,42 @@ pub fn prologue() -> String {
         &optimizations::is_resolved::rules().join("\n"),
         &optimizations::body_contains::rules().join("\n"),
         include_str!("optimizations/purity_analysis.egg"),
-        // TODO cond inv code motion with regions
-        //&optimizations::conditional_invariant_code_motion::rules().join("\n"),
         include_str!("utility/add_context.egg"),
         include_str!("utility/context-prop.egg"),
         include_str!("utility/term-subst.egg"),
         include_str!("utility/context_of.egg"),
         include_str!("utility/subst.egg"),
+        // canonicalization checks bounds before adding 1 to an integer
+        include_str!("interval_analysis.egg"),
+        include_str!("known_bits.egg"),
         include_str!("utility/canonicalize.egg"),
         include_str!("utility/expr_size.egg"),
         include_str!("utility/drop_at.egg"),
-        include_str!("interval_analysis.egg"),
         include_str!("optimizations/switch_rewrites.egg"),
         include_str!("optimizations/select.egg"),
         include_str!("optimizations/peepholes.egg"),
+        include_str!("optimizations/fast_math.egg"),
+        include_str!("optimizations/div_by_const.egg"),
         &optimizations::memory::rules(),
         include_str!("optimizations/memory.egg"),
+        &optimizations::memory::loop_iteration_rules(),
         include_str!("optimizations/mem_simple.egg"),
+        include_str!("optimizations/dead_store.egg"),
         &optimizations::loop_invariant::rules().join("\n"),
+        include_str!("optimizations/load_hoisting.egg"),
         include_str!("optimizations/loop_simplify.egg"),
         include_str!("optimizations/loop_unroll.egg"),
+        include_str!("optimizations/bounds_checks.egg"),
+        &optimizations::loop_fusion::rules().join("\n"),
         include_str!("optimizations/swap_if.egg"),
         include_str!("optimizations/rec_to_loop.egg"),
         include_str!("optimizations/passthrough.egg"),
         include_str!("optimizations/loop_strength_reduction.egg"),
         include_str!("optimizations/ivt.egg"),
         include_str!("optimizations/conditional_invariant_code_motion.egg"),
+        include_str!("optimizations/partial_redundancy.egg"),
+        include_str!("optimizations/code_placement.egg"),
+        include_str!("optimizations/branch_threading.egg"),
         include_str!("optimizations/conditional_push_in.egg"),
         include_str!("utility/debug-helper.egg"),
         include_str!("optimizations/hackers_delight.egg"),
@@ -193,8 +213,10 @@ pub fn print_with_intermediate_vars(termdag: &TermDag, term: Term) -> String {
 // Build an egglog program that optimizes a particular batch of functions `fns`
 // with a schedule `schedule`.
 // Adds context to the program before optimizing.
-// If `inline_program` is true, it also inlines calls in `fns`.
+// If `inline_program` is true, it also inlines calls in `fns`, choosing callees using `inline_policy`.
 // `inline_program` is the program to inline calls from, allowing us to inline unoptimized function bodies.
+// If `interchange_loops` is true, loop nests are also added in interchanged order.
+#[allow(clippy::too_many_arguments)]
 pub fn build_program(
     program: &TreeProgram,
     inline_program: Option<&TreeProgram>,
@@ -202,21 +224,19 @@ pub fn build_program(
     schedule: &str,
     ablate: Option<&str>,
     use_context: bool,
+    interchange_loops: bool,
+    inline_policy: &InlinePolicy,
 ) -> String {
     // inlining first before adding context
     let to_inline = inline_program.unwrap_or(program);
     let inlined = if inline_program.is_some() {
-        perform_inlining(
-            to_inline,
-            fns.to_vec(),
-            config::FUNCTION_INLINING_ITERATIONS,
-        )
+        perform_inlining(to_inline, fns.to_vec(), inline_policy)
     } else {
         program.clone()
     };
 
     // Then add context or dummy context based on flag
-    let (program, context_cache) = if use_context {
+    let (program, mut context_cache) = if use_context {
         inlined.add_context()
     } else {
         inlined.add_dummy_ctx()
@@ -242,6 +262,20 @@ pub fn build_program(
         );
     }
 
+    // offer loop nests in the other order, which adds loop contexts
+    let _interchanged_nests = if interchange_loops {
+        interchange_unions(
+            &program,
+            fns,
+            &mut context_cache,
+            &mut printed,
+            &mut tree_state,
+            &mut term_cache,
+        )
+    } else {
+        vec![]
+    };
+
     let loop_context_unions =
         context_cache.get_unions_with_sharing(&mut printed, &mut tree_state, &mut term_cache);
 
@@ -258,6 +292,13 @@ pub fn build_program(
         .unwrap();
     }
 
+    // bounds on function arguments known from every call site
+    printed.push_str(&arg_bound_facts(&inlined));
+    // effect summaries of each function, computed on the call graph
+    printed.push_str(&effect_facts(&inlined));
+    // magic numbers for division by constants
+    printed.push_str(&div_magic_facts(&inlined));
+
     let prologue = prologue();
     let (prologue, schedule) = if let Some(ablate) = ablate {
         (
@@ -314,7 +355,16 @@ pub fn are_progs_eq(program1: TreeProgram, program2: TreeProgram) -> bool {
 pub fn check_roundtrip_egraph(program: &TreeProgram) {
     let mut termdag = egglog::TermDag::default();
     let fns = program.fns();
-    let egglog_prog = build_program(program, None, &fns, "", None, true);
+    let egglog_prog = build_program(
+        program,
+        None,
+        &fns,
+        "",
+        None,
+        true,
+        false,
+        &InlinePolicy::default(),
+    );
     log::info!("Running egglog program...");
     let mut egraph = egglog::EGraph::default();
     egraph.parse_and_run_program(None, &egglog_prog).unwrap();
@@ -382,6 +432,17 @@ pub struct EggccConfig {
     pub ilp_solver: IlpSolver,
     /// When set, dump the serialized e-graphs sent to tiger into this directory.
     pub egraph_dump_dir: Option<PathBuf>,
+    /// Which calls to inline on the `InlineWithSchedule` pass.
+    pub inline_policy: InlinePolicy,
+    /// Before the `InlineWithSchedule` pass, clone functions that are called
+    /// with constant arguments and specialize the clones to those constants.
+    pub specialize_functions: bool,
+    /// Also add nested array loops to the e-graph with the loops swapped,
+    /// when that is legal and changes how many accesses have unit stride.
+    pub interchange_loops: bool,
+    /// Enable float rewrites that are only valid up to rounding,
+    /// like reassociation and multiplying by the reciprocal.
+    pub fast_math: bool,
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -431,7 +492,7 @@ impl EggccConfig {
     pub fn get_schedule_list(&self) -> Vec<CompilerPass> {
         match self.schedule {
             Schedule::Parallel => parallel_schedule(self),
-            Schedule::Sequential => schedule::mk_sequential_schedule(),
+            Schedule::Sequential => schedule::mk_sequential_schedule(self),
         }
     }
 
@@ -462,6 +523,10 @@ impl Default for EggccConfig {
             ilp_minimize_objective: true,
             ilp_solver: IlpSolver::default(),
             egraph_dump_dir: None,
+            inline_policy: InlinePolicy::default(),
+            specialize_functions: true,
+            interchange_loops: true,
+            fast_math: false,
         }
     }
 }
@@ -839,6 +904,22 @@ pub fn optimize(
         }
 
         log::info!("Running pass {}...", i);
+        if let schedule::CompilerPass::InlineWithSchedule(_) = schedule {
+            // specialize functions along with inlining, so that the clones
+            // are optimized together with their callers
+            if eggcc_config.specialize_functions {
+                res = specialize_functions(&res);
+            }
+            // recursive calls are never inlined, so turn tail recursion into loops
+            res = tail_recursion_to_loops(&res);
+            // inlining exposes allocations that never leave the caller
+            res = scalar_replace_allocations(&res);
+        }
+        // functions that are no longer called (e.g. after specialization) are removed,
+        // unless we were asked to optimize specific functions
+        if eggcc_config.optimize_functions.is_none() {
+            res = res.remove_dead_functions();
+        }
         let fns = res.fns();
 
         // if we are inlining, save the program
@@ -878,6 +959,8 @@ pub fn optimize(
                 schedule.egglog_schedule(),
                 eggcc_config.ablate.as_deref(),
                 eggcc_config.use_context,
+                eggcc_config.interchange_loops,
+                &eggcc_config.inline_policy,
             );
 
             log::info!("Running egglog program...");
@@ -4,6 +4,7 @@
 
 use std::{collections::HashSet, rc::Rc};
 
+use crate::optimizations::effect_summary::{function_effects, Effect};
 use crate::schema::{BinaryOp, Expr, RcExpr, TernaryOp, TreeProgram, Type};
 use crate::typechecker::TypeChecker;
 use egglog::Term;
@@ -194,13 +195,24 @@ impl<'a> Extractor<'a> {
 /// Check that a program is linear in its use of state.
 #[allow(dead_code)]
 pub fn check_program_is_linear(prog: &TreeProgram) -> Result<(), String> {
+    let effects = function_effects(prog);
     for func in &prog.functions {
-        check_function_is_linear(func, prog)?;
+        check_function_is_linear_with_effects(func, prog, &effects)?;
     }
-    check_function_is_linear(&prog.entry, prog)
+    check_function_is_linear_with_effects(&prog.entry, prog, &effects)
 }
 
 pub fn check_function_is_linear(fun: &RcExpr, prog_for_types: &TreeProgram) -> Result<(), String> {
+    check_function_is_linear_with_effects(fun, prog_for_types, &function_effects(prog_for_types))
+}
+
+/// Like `check_function_is_linear`, with the effect summaries of
+/// `prog_for_types` computed once by the caller.
+pub(crate) fn check_function_is_linear_with_effects(
+    fun: &RcExpr,
+    prog_for_types: &TreeProgram,
+    effects: &IndexMap<String, Effect>,
+) -> Result<(), String> {
     let mut reachables: IndexMap<*const Expr, IndexSet<*const Expr>> = Default::default();
     let mut raw_to_rc: IndexMap<*const Expr, RcExpr> = Default::default();
     let fun_body = fun.func_body().unwrap();
@@ -233,16 +245,20 @@ pub fn check_function_is_linear(fun: &RcExpr, prog_for_types: &TreeProgram) -> R
         }
         None
     };
+    let readers = find_pure_call_readers(&reachables, &raw_to_rc, &effectful_cache, effects);
     for (region, exprs) in reachables {
         // consume map
         let mut dangling_effectful: HashSet<*const Expr> = HashSet::new();
         for &e in &exprs {
-            if get_if_effectful(e).is_some() {
+            if get_if_effectful(e).is_some() && !readers.contains(&e) {
                 dangling_effectful.insert(e);
             }
         }
         let mut effectful_parent: IndexMap<*const Expr, RcExpr> = Default::default();
         for expr_ptr in exprs {
+            if readers.contains(&expr_ptr) {
+                continue;
+            }
             if let Some(expr) = get_if_effectful(expr_ptr) {
                 if !matches!(expr.as_ref(), Expr::Arg(..)) {
                     let children = expr.children_same_scope();
@@ -284,6 +300,76 @@ pub fn check_function_is_linear(fun: &RcExpr, prog_for_types: &TreeProgram) -> R
     Ok(())
 }
 
+/// Calls to pure functions leave the state unchanged.
+/// When nothing uses their state output, they only read the state edge
+/// instead of consuming it, so they don't need to be on the state edge path.
+/// Returns these calls along with the tuple nodes that only pass the state into them.
+fn find_pure_call_readers(
+    reachables: &IndexMap<*const Expr, IndexSet<*const Expr>>,
+    raw_to_rc: &IndexMap<*const Expr, RcExpr>,
+    effectful_cache: &IndexMap<*const Expr, bool>,
+    effects: &IndexMap<String, Effect>,
+) -> HashSet<*const Expr> {
+    let is_effectful = |expr: &RcExpr| {
+        effectful_cache
+            .get(&Rc::as_ptr(expr))
+            .copied()
+            .unwrap_or(false)
+    };
+    // effectful parents of each effectful expression
+    let mut consumers: IndexMap<*const Expr, Vec<*const Expr>> = Default::default();
+    for expr in raw_to_rc.values() {
+        if !is_effectful(expr) || matches!(expr.as_ref(), Expr::Function(..)) {
+            continue;
+        }
+        for child in expr.children_same_scope() {
+            if is_effectful(&child) {
+                consumers
+                    .entry(Rc::as_ptr(&child))
+                    .or_default()
+                    .push(Rc::as_ptr(expr));
+            }
+        }
+    }
+
+    let mut readers = HashSet::new();
+    for (ptr, expr) in raw_to_rc {
+        let Expr::Call(name, args) = expr.as_ref() else {
+            continue;
+        };
+        if effects.get(name) != Some(&Effect::Pure)
+            || consumers.contains_key(ptr)
+            || reachables.contains_key(ptr)
+        {
+            continue;
+        }
+        readers.insert(*ptr);
+
+        // the tuple nodes leading to the state are also only read
+        let mut current = args.clone();
+        while is_effectful(&current)
+            && consumers
+                .get(&Rc::as_ptr(&current))
+                .is_some_and(|parents| parents.iter().all(|parent| readers.contains(parent)))
+        {
+            let next = match current.as_ref() {
+                Expr::Single(inner) => inner.clone(),
+                Expr::Concat(left, right) => {
+                    if is_effectful(left) {
+                        left.clone()
+                    } else {
+                        right.clone()
+                    }
+                }
+                _ => break,
+            };
+            readers.insert(Rc::as_ptr(&current));
+            current = next;
+        }
+    }
+    readers
+}
+
 impl Expr {
     /// Populates the reachable_from table, which is a map from the root of the region
     /// to the set of reachable nodes, stored as raw pointers.
@@ -0,0 +1,118 @@
+#[cfg(test)]
+use crate::egglog_test;
+
+#[test]
+fn loop_bounds_check() -> crate::Result {
+    use crate::ast::*;
+
+    // if 0 < n {
+    //   i = 0; sum = 0
+    //   do {
+    //     sum = if i < n {sum + i} else {-1000}
+    //     i = i + 1
+    //   } while (i < n)
+    // }
+    // the check i < n holds in every iteration
+    let next = add(getat(0), int(1));
+    let checked = get(
+        tif(
+            less_than(getat(0), getat(1)),
+            parallel!(getat(0), getat(2)),
+            single(add(getat(1), getat(0))),
+            single(int(-1000)),
+        ),
+        0,
+    );
+    let lp = dowhile(
+        parallel!(int(0), getat(0), int(0)),
+        parallel!(less_than(next.clone(), getat(1)), next, getat(1), checked),
+    );
+    let body = get(
+        tif(
+            less_than(int(0), arg()),
+            single(arg()),
+            single(get(lp, 2)),
+            single(int(0)),
+        ),
+        0,
+    );
+
+    let f = function("main", base(intt()), base(intt()), body).func_with_arg_types();
+    let prog = f.to_program(base(intt()), base(intt()));
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "(check (= (BoolB true)
+                   (lo-bound (Bop (LessThan) (Get (Arg ty (InLoop ins body)) 0)
+                                             (Get (Arg ty (InLoop ins body)) 1)))))",
+        vec![with_context],
+        intv(5),
+        intv(10),
+        vec![],
+    )
+}
+
+#[test]
+fn alloc_size_bounds_check_removed() -> crate::Result {
+    use crate::ast::*;
+
+    // arr = alloc n
+    // if 0 < n {
+    //   i = 0; bound = n; len = n
+    //   do {
+    //     if i < len {arr[i] = i} else {abort}
+    //     i = i + 1
+    //   } while (i < bound)
+    //   arr[n - 1]
+    // }
+    // len and bound are both the size of arr, so the check always holds
+    let next = add(getat(0), int(1));
+    let checked = get(
+        tif(
+            less_than(getat(0), getat(3)),
+            parallel!(getat(1), getat(0), getat(4)),
+            single(write(ptradd(getat(0), getat(1)), getat(1), getat(2))),
+            single(getat(2)),
+        ),
+        0,
+    );
+    let lp = dowhile(
+        parallel!(int(0), getat(1), getat(0), getat(0), getat(2)),
+        parallel!(
+            less_than(next.clone(), getat(2)),
+            next,
+            getat(1),
+            getat(2),
+            getat(3),
+            checked
+        ),
+    );
+    let last = load(
+        ptradd(get(lp.clone(), 1), sub(getat(0), int(1))),
+        get(lp.clone(), 4),
+    );
+    let arr = alloc(0, getat(0), getat(1), pointert(intt()));
+    let body = tif(
+        less_than(int(0), getat(0)),
+        parallel!(getat(0), get(arr.clone(), 0), get(arr, 1)),
+        parallel!(get(last.clone(), 0), free(get(lp, 1), get(last, 1))),
+        parallel!(int(0), free(getat(1), getat(2))),
+    );
+
+    let ty = tuplet!(intt(), statet());
+    let prog = program!(function("main", ty.clone(), ty, body),).with_arg_types();
+    let (with_context, cache) = prog.add_context();
+
+    // the check i < len holds in every iteration
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "(check (= (BoolB true)
+                   (lo-bound (Bop (LessThan) (Get (Arg ty (InLoop ins body)) 0)
+                                             (Get (Arg ty (InLoop ins body)) 3)))))",
+        vec![with_context],
+        tuplev!(intv(5), statev()),
+        tuplev!(intv(4), statev()),
+        vec![],
+    )
+}
@@ -0,0 +1,78 @@
+#[cfg(test)]
+use crate::egglog_test;
+
+#[test]
+fn thread_predicate_variable() -> crate::Result {
+    use crate::ast::*;
+
+    // the first if computes a flag that the second if tests,
+    // so the second if is threaded into the arms of the first
+    let ty = tuplet!(intt());
+    let first = tif(
+        less_than(getat(0), int(0)),
+        parallel!(getat(0)),
+        parallel!(ttrue(), getat(0)),
+        parallel!(tfalse(), getat(0)),
+    );
+    let second = tif(
+        get(first.clone(), 0),
+        parallel!(get(first.clone(), 1), add(get(first, 1), int(3))),
+        single(mul(getat(1), int(2))),
+        single(add(getat(0), int(1))),
+    );
+    let prog = function("main", ty.clone(), ty.clone(), second)
+        .func_with_arg_types()
+        .to_program(ty.clone(), ty.clone());
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "
+(check (= first (If pred inputs thn els))
+       (= second (If (Get first 0) ins2 thn2 els2))
+       (= second (If pred inputs threaded-thn threaded-els)))",
+        vec![with_context],
+        tuplev!(intv(-2)),
+        tuplev!(intv(2)),
+        vec![],
+    )
+}
+
+#[test]
+fn threaded_if_folds_away() -> crate::Result {
+    use crate::ast::*;
+
+    // the second if tests a flag set to a constant in each arm of the first,
+    // so after threading it folds away in both arms
+    let ty = tuplet!(intt(), statet());
+    let first = tif(
+        less_than(getat(0), int(0)),
+        parallel!(getat(0), getat(1)),
+        parallel!(ttrue(), getat(0), getat(1)),
+        parallel!(tfalse(), getat(0), getat(1)),
+    );
+    let second = tif(
+        get(first.clone(), 0),
+        parallel!(mul(get(first.clone(), 1), int(3)), get(first, 2)),
+        parallel!(getat(0), tprint(getat(0), getat(1))),
+        parallel!(int(0), getat(1)),
+    );
+    let prog = function("main", ty.clone(), ty.clone(), second)
+        .func_with_arg_types()
+        .to_program(ty.clone(), ty.clone());
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "
+(check (= first (If pred inputs thn els))
+       (= second (If (Get first 0) ins2 thn2 els2))
+       (= second (If pred inputs threaded-thn threaded-els))
+       (= (Get threaded-thn 0) (Bop (Mul) x (Const (Int 3) ty ctx)))
+       (= (Get threaded-els 0) (Const (Int 0) ty2 ctx2)))",
+        vec![with_context],
+        tuplev!(intv(-2), statev()),
+        tuplev!(intv(-6), statev()),
+        vec!["-6".to_string()],
+    )
+}
@@ -0,0 +1,258 @@
+//! A call graph over a `TreeProgram`.
+//! Used by the interprocedural passes (inlining, specialization, dead function elimination)
+//! to find call sites and recursive strongly connected components.
+
+use std::rc::Rc;
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::schema::{Expr, RcExpr, TreeProgram};
+
+pub(crate) struct CallGraph {
+    /// For each function, the functions it calls and how many call sites there are for each.
+    callees: IndexMap<String, IndexMap<String, usize>>,
+    /// For each function, the number of call sites in the whole program.
+    call_sites: IndexMap<String, usize>,
+    /// Strongly connected components, callees before callers.
+    sccs: Vec<Vec<String>>,
+    /// Functions that are part of a cycle in the call graph,
+    /// including functions that call themselves.
+    recursive: IndexSet<String>,
+}
+
+/// Finds all the `Call`s in `expr`, including ones in nested regions.
+/// Shared calls are only returned once, since they are a single call site.
+pub(crate) fn calls_in_expr(expr: &RcExpr) -> Vec<RcExpr> {
+    let mut seen = IndexSet::new();
+    let mut res = vec![];
+    let mut todo = vec![expr.clone()];
+    while let Some(expr) = todo.pop() {
+        if !seen.insert(Rc::as_ptr(&expr)) {
+            continue;
+        }
+        if let Expr::Call(..) = expr.as_ref() {
+            res.push(expr.clone());
+        }
+        todo.extend(expr.children_exprs());
+    }
+    res
+}
+
+/// All the call sites in the program, grouped by callee.
+pub(crate) fn call_sites(program: &TreeProgram) -> IndexMap<String, Vec<RcExpr>> {
+    let mut res: IndexMap<String, Vec<RcExpr>> = IndexMap::new();
+    for name in program.fns() {
+        let body = program.get_function(&name).unwrap().func_body().unwrap();
+        for call in calls_in_expr(body) {
+            let Expr::Call(callee, _) = call.as_ref() else {
+                unreachable!()
+            };
+            res.entry(callee.clone()).or_default().push(call);
+        }
+    }
+    res
+}
+
+impl CallGraph {
+    pub(crate) fn new(program: &TreeProgram) -> Self {
+        let mut callees: IndexMap<String, IndexMap<String, usize>> = IndexMap::new();
+        let mut call_sites: IndexMap<String, usize> = IndexMap::new();
+        for name in program.fns() {
+            call_sites.entry(name.clone()).or_insert(0);
+            let func = program.get_function(&name).unwrap();
+            let body = func.func_body().expect("Expected function body");
+            let func_callees = callees.entry(name.clone()).or_default();
+            for call in calls_in_expr(body) {
+                let Expr::Call(callee, _) = call.as_ref() else {
+                    unreachable!()
+                };
+                *func_callees.entry(callee.clone()).or_insert(0) += 1;
+                *call_sites.entry(callee.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let mut graph = CallGraph {
+            callees,
+            call_sites,
+            sccs: vec![],
+            recursive: IndexSet::new(),
+        };
+        graph.sccs = Tarjan::run(&graph);
+        let recursive = graph
+            .sccs
+            .iter()
+            .filter(|scc| scc.len() > 1 || graph.calls(&scc[0], &scc[0]))
+            .flatten()
+            .cloned()
+            .collect();
+        graph.recursive = recursive;
+        graph
+    }
+
+    /// The functions directly called by `func`.
+    pub(crate) fn callees(&self, func: &str) -> impl Iterator<Item = &String> {
+        self.callees.get(func).into_iter().flat_map(|c| c.keys())
+    }
+
+    pub(crate) fn calls(&self, caller: &str, callee: &str) -> bool {
+        self.callees
+            .get(caller)
+            .is_some_and(|c| c.contains_key(callee))
+    }
+
+    /// How many call sites of `func` there are in the whole program.
+    pub(crate) fn num_call_sites(&self, func: &str) -> usize {
+        self.call_sites.get(func).copied().unwrap_or(0)
+    }
+
+    /// A function is a leaf when it makes no calls.
+    pub(crate) fn is_leaf(&self, func: &str) -> bool {
+        self.callees(func).next().is_none()
+    }
+
+    pub(crate) fn is_recursive(&self, func: &str) -> bool {
+        self.recursive.contains(func)
+    }
+
+    /// Strongly connected components of the call graph,
+    /// in reverse topological order (callees come before their callers).
+    pub(crate) fn sccs(&self) -> &[Vec<String>] {
+        &self.sccs
+    }
+
+    /// All the functions transitively reachable from `root`, including `root`.
+    pub(crate) fn reachable_from(&self, root: &str) -> IndexSet<String> {
+        let mut reachable = IndexSet::new();
+        let mut todo = vec![root.to_string()];
+        while let Some(func) = todo.pop() {
+            if reachable.insert(func.clone()) {
+                todo.extend(self.callees(&func).cloned());
+            }
+        }
+        reachable
+    }
+}
+
+/// Tarjan's strongly connected components algorithm.
+/// Produces components in reverse topological order.
+struct Tarjan<'a> {
+    graph: &'a CallGraph,
+    index: IndexMap<String, usize>,
+    lowlink: IndexMap<String, usize>,
+    stack: Vec<String>,
+    on_stack: IndexSet<String>,
+    sccs: Vec<Vec<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn run(graph: &'a CallGraph) -> Vec<Vec<String>> {
+        let mut tarjan = Tarjan {
+            graph,
+            index: IndexMap::new(),
+            lowlink: IndexMap::new(),
+            stack: vec![],
+            on_stack: IndexSet::new(),
+            sccs: vec![],
+        };
+        for func in graph.callees.keys() {
+            if !tarjan.index.contains_key(func) {
+                tarjan.visit(func);
+            }
+        }
+        tarjan.sccs
+    }
+
+    fn visit(&mut self, func: &str) {
+        let idx = self.index.len();
+        self.index.insert(func.to_string(), idx);
+        self.lowlink.insert(func.to_string(), idx);
+        self.stack.push(func.to_string());
+        self.on_stack.insert(func.to_string());
+
+        let callees: Vec<String> = self.graph.callees(func).cloned().collect();
+        for callee in callees {
+            if !self.index.contains_key(&callee) {
+                self.visit(&callee);
+                let low = self.lowlink[func].min(self.lowlink[&callee]);
+                self.lowlink.insert(func.to_string(), low);
+            } else if self.on_stack.contains(&callee) {
+                let low = self.lowlink[func].min(self.index[&callee]);
+                self.lowlink.insert(func.to_string(), low);
+            }
+        }
+
+        if self.lowlink[func] == self.index[func] {
+            let mut scc = vec![];
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack.swap_remove(&member);
+                let done = member == func;
+                scc.push(member);
+                if done {
+                    break;
+                }
+            }
+            self.sccs.push(scc);
+        }
+    }
+}
+
+#[test]
+fn test_call_graph_recursion() {
+    use crate::ast::*;
+    let prog = program!(
+        function("main", base(intt()), base(intt()), call("even", arg())),
+        function(
+            "even",
+            base(intt()),
+            base(intt()),
+            tif(
+                eq(arg(), int(0)),
+                arg(),
+                int(1),
+                call("odd", sub(arg(), int(1)))
+            )
+        ),
+        function(
+            "odd",
+            base(intt()),
+            base(intt()),
+            tif(
+                eq(arg(), int(0)),
+                arg(),
+                int(0),
+                call("even", sub(arg(), int(1)))
+            )
+        ),
+        function("unused", base(intt()), base(intt()), call("helper", arg())),
+        function("helper", base(intt()), base(intt()), add(arg(), int(1))),
+    );
+    let call_graph = CallGraph::new(&prog);
+
+    assert!(!call_graph.is_recursive("main"));
+    assert!(call_graph.is_recursive("even"));
+    assert!(call_graph.is_recursive("odd"));
+    assert!(!call_graph.is_recursive("helper"));
+    assert!(call_graph.is_leaf("helper"));
+    assert_eq!(call_graph.num_call_sites("helper"), 1);
+    assert_eq!(call_graph.num_call_sites("main"), 0);
+
+    let reachable = call_graph.reachable_from("main");
+    assert!(reachable.contains("odd"));
+    assert!(!reachable.contains("unused"));
+    assert!(!reachable.contains("helper"));
+
+    // helper is a callee of unused, so it comes first
+    let position = |name: &str| {
+        call_graph
+            .sccs()
+            .iter()
+            .position(|scc| scc.iter().any(|f| f == name))
+            .unwrap()
+    };
+    assert!(position("helper") < position("unused"));
+    assert_eq!(
+        call_graph.sccs()[position("even")],
+        call_graph.sccs()[position("odd")]
+    );
+}
@@ -0,0 +1,104 @@
+#[cfg(test)]
+use crate::egglog_test;
+
+#[test]
+fn sink_input_into_branch() -> crate::Result {
+    use crate::ast::*;
+
+    // x * y is only used by the then branch,
+    // so the branch can compute it from its own arguments
+    let ty = tuplet!(intt(), intt());
+    let body = parallel!(get(
+        tif(
+            less_than(getat(0), int(0)),
+            parallel!(getat(0), getat(1), mul(getat(0), getat(1))),
+            single(getat(2)),
+            single(int(0)),
+        ),
+        0
+    ));
+    let prog = function("main", ty.clone(), tuplet!(intt()), body)
+        .func_with_arg_types()
+        .to_program(ty.clone(), tuplet!(intt()));
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "
+(check (= arg (Arg ty (InIf true pred inputs)))
+       (= (Get arg 2) (Bop (Mul) (Get arg 0) (Get arg 1))))",
+        vec![with_context],
+        tuplev!(intv(-2), intv(3)),
+        tuplev!(intv(-6)),
+        vec![],
+    )
+}
+
+#[test]
+fn if_drops_recomputed_input() -> crate::Result {
+    use crate::ast::*;
+
+    // m = x * y
+    // if x < 0 {m} else {y}
+    // both branches can compute m, so the if doesn't need it as an input
+    let ty = tuplet!(intt(), intt());
+    let body = single(get(
+        tif(
+            less_than(getat(0), int(0)),
+            parallel!(getat(0), getat(1), mul(getat(0), getat(1))),
+            single(getat(2)),
+            single(getat(1)),
+        ),
+        0,
+    ));
+    let prog = function("main", ty.clone(), tuplet!(intt()), body)
+        .func_with_arg_types()
+        .to_program(ty.clone(), tuplet!(intt()));
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "
+(check (= if_e (If pred inputs thn els))
+       (= if_e (If pred (TupleRemoveAt inputs 2) thn2 els2)))",
+        vec![with_context],
+        tuplev!(intv(-2), intv(3)),
+        tuplev!(intv(-6)),
+        vec![],
+    )
+}
+
+#[test]
+fn switch_drops_recomputed_input() -> crate::Result {
+    use crate::ast::*;
+
+    // m = x * y
+    // switch x {m, 0, y}
+    // every branch can compute m, so the switch doesn't need it as an input
+    let ty = tuplet!(intt(), intt());
+    let body = single(get(
+        switch!(
+            getat(0),
+            parallel!(getat(0), getat(1), mul(getat(0), getat(1)));
+            single(getat(2)),
+            single(int(0)),
+            single(getat(1)),
+        ),
+        0,
+    ));
+    let prog = function("main", ty.clone(), tuplet!(intt()), body)
+        .func_with_arg_types()
+        .to_program(ty.clone(), tuplet!(intt()));
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "
+(check (= s (Switch pred inputs branches))
+       (= s (Switch pred (TupleRemoveAt inputs 2) branches2)))",
+        vec![with_context],
+        tuplev!(intv(2), intv(3)),
+        tuplev!(intv(3)),
+        vec![],
+    )
+}
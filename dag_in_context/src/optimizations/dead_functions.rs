@@ -0,0 +1,266 @@
+//! Whole-program dead code elimination.
+//! `remove_dead_code_nodes` works within a single function.
+//! This pass removes functions that are unreachable from the entry,
+//! arguments that a function never reads, and return values that no caller reads.
+//! Call sites are rewritten to match the new signatures.
+
+use std::rc::Rc;
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    ast::parallel_vec_ty,
+    optimizations::call_graph::{call_sites, CallGraph},
+    remove_dead_code_nodes::{
+        arg_indices_used, remove_dead_args, remove_dead_code_ty, try_split_inputs,
+    },
+    schema::{Expr, RcExpr, TreeProgram, Type},
+};
+
+/// Rebuilds `expr` bottom-up, applying `rewrite` to every node after its children.
+fn rewrite_bottom_up(
+    expr: &RcExpr,
+    cache: &mut IndexMap<*const Expr, RcExpr>,
+    rewrite: &impl Fn(RcExpr) -> RcExpr,
+) -> RcExpr {
+    if let Some(res) = cache.get(&Rc::as_ptr(expr)) {
+        return res.clone();
+    }
+    let res = rewrite(expr.map_expr_children(|child| rewrite_bottom_up(child, cache, rewrite)));
+    cache.insert(Rc::as_ptr(expr), res.clone());
+    res
+}
+
+/// Index `index` after removing `dead_indices` from a tuple.
+fn shift_index(index: usize, dead_indices: &[usize]) -> usize {
+    index - dead_indices.iter().filter(|dead| **dead < index).count()
+}
+
+impl TreeProgram {
+    /// Removes functions unreachable from the entry, unused arguments
+    /// and unused return values. The entry function's signature is never changed.
+    /// State arguments and return values are always kept.
+    pub fn remove_dead_functions(&self) -> TreeProgram {
+        let entry_name = self.entry.func_name().unwrap();
+        let reachable = CallGraph::new(self).reachable_from(&entry_name);
+        let mut res = TreeProgram {
+            entry: self.entry.clone(),
+            functions: self
+                .functions
+                .iter()
+                .filter(|func| reachable.contains(&func.func_name().unwrap()))
+                .cloned()
+                .collect(),
+        };
+
+        // Removing a return value can make an argument dead and vice versa.
+        // Each round removes at least one, so this terminates.
+        loop {
+            let removed_args = res.remove_dead_args_once();
+            let removed_outputs = res.remove_dead_outputs_once();
+            if !removed_args && !removed_outputs {
+                break;
+            }
+        }
+        res
+    }
+
+    /// Applies `rewrite` to every node in every function.
+    fn rewrite_all_bottom_up(&mut self, rewrite: impl Fn(RcExpr) -> RcExpr) {
+        let mut cache = IndexMap::new();
+        self.entry = rewrite_bottom_up(&self.entry, &mut cache, &rewrite);
+        self.functions = self
+            .functions
+            .iter()
+            .map(|func| rewrite_bottom_up(func, &mut cache, &rewrite))
+            .collect();
+    }
+
+    fn remove_dead_args_once(&mut self) -> bool {
+        let sites = call_sites(self);
+        let mut dead_args: IndexMap<String, Vec<usize>> = IndexMap::new();
+        for func in self.functions.iter_mut() {
+            let name = func.func_name().unwrap();
+            let Type::TupleT(input_tys) = func.func_input_ty().unwrap() else {
+                continue;
+            };
+            // we can only drop arguments from call sites that pass an explicit tuple
+            let explicit_args = sites.get(&name).into_iter().flatten().all(|call| {
+                let Expr::Call(_, args) = call.as_ref() else {
+                    unreachable!()
+                };
+                try_split_inputs(args.clone()).is_some()
+            });
+            if !explicit_args {
+                continue;
+            }
+
+            let body = func.func_body().unwrap();
+            let used = arg_indices_used(body);
+            let dead: Vec<usize> = (0..input_tys.len())
+                .filter(|i| !used.contains(i) && !input_tys[*i].contains_state())
+                .collect();
+            if dead.is_empty() {
+                continue;
+            }
+
+            log::info!("Removing unused arguments {dead:?} of {name}");
+            *func = Rc::new(Expr::Function(
+                name.clone(),
+                remove_dead_code_ty(Type::TupleT(input_tys), &dead),
+                func.func_output_ty().unwrap(),
+                remove_dead_args(body, &dead),
+            ));
+            dead_args.insert(name, dead);
+        }
+        if dead_args.is_empty() {
+            return false;
+        }
+
+        self.rewrite_all_bottom_up(|expr| match expr.as_ref() {
+            Expr::Call(name, args) if dead_args.contains_key(name) => {
+                let dead = &dead_args[name];
+                let live = try_split_inputs(args.clone())
+                    .unwrap()
+                    .into_iter()
+                    .enumerate()
+                    .filter(|(i, _)| !dead.contains(i))
+                    .map(|(_, arg)| arg);
+                Rc::new(Expr::Call(
+                    name.clone(),
+                    parallel_vec_ty(live.collect::<Vec<_>>(), args.get_arg_type()),
+                ))
+            }
+            _ => expr,
+        });
+        true
+    }
+
+    /// For each function, which of its outputs are read by callers.
+    /// `None` when the whole output tuple is used (e.g. returned directly).
+    fn outputs_used(&self) -> IndexMap<String, Option<IndexSet<usize>>> {
+        let mut res: IndexMap<String, Option<IndexSet<usize>>> = IndexMap::new();
+        let mut use_output = |callee: &str, index: Option<usize>| {
+            let entry = res
+                .entry(callee.to_string())
+                .or_insert_with(|| Some(IndexSet::new()));
+            match (entry, index) {
+                (Some(used), Some(index)) => {
+                    used.insert(index);
+                }
+                (entry, _) => *entry = None,
+            }
+        };
+
+        let mut seen = IndexSet::new();
+        for name in self.fns() {
+            let body = self.get_function(&name).unwrap().func_body().unwrap();
+            if let Expr::Call(callee, _) = body.as_ref() {
+                use_output(callee, None);
+            }
+            let mut todo = vec![body.clone()];
+            while let Some(parent) = todo.pop() {
+                if !seen.insert(Rc::as_ptr(&parent)) {
+                    continue;
+                }
+                for child in parent.children_exprs() {
+                    if let Expr::Call(callee, _) = child.as_ref() {
+                        match parent.as_ref() {
+                            Expr::Get(_, index) => use_output(callee, Some(*index)),
+                            _ => use_output(callee, None),
+                        }
+                    }
+                    todo.push(child);
+                }
+            }
+        }
+        res
+    }
+
+    fn remove_dead_outputs_once(&mut self) -> bool {
+        let outputs_used = self.outputs_used();
+        let mut dead_outputs: IndexMap<String, Vec<usize>> = IndexMap::new();
+        for func in self.functions.iter_mut() {
+            let name = func.func_name().unwrap();
+            let Type::TupleT(output_tys) = func.func_output_ty().unwrap() else {
+                continue;
+            };
+            let used = match outputs_used.get(&name) {
+                Some(Some(used)) => used.clone(),
+                // the whole output is used, or the function is never called
+                Some(None) | None => continue,
+            };
+            let body = func.func_body().unwrap();
+            let Some(outputs) = try_split_inputs(body.clone()) else {
+                continue;
+            };
+            let dead: Vec<usize> = (0..output_tys.len())
+                .filter(|i| !used.contains(i) && !output_tys[*i].contains_state())
+                .collect();
+            if dead.is_empty() {
+                continue;
+            }
+
+            log::info!("Removing unused outputs {dead:?} of {name}");
+            let live = outputs
+                .into_iter()
+                .enumerate()
+                .filter(|(i, _)| !dead.contains(i))
+                .map(|(_, output)| output);
+            *func = Rc::new(Expr::Function(
+                name.clone(),
+                func.func_input_ty().unwrap(),
+                remove_dead_code_ty(Type::TupleT(output_tys), &dead),
+                parallel_vec_ty(live.collect::<Vec<_>>(), body.get_arg_type()),
+            ));
+            dead_outputs.insert(name, dead);
+        }
+        if dead_outputs.is_empty() {
+            return false;
+        }
+
+        self.rewrite_all_bottom_up(|expr| match expr.as_ref() {
+            Expr::Get(call, index) => match call.as_ref() {
+                Expr::Call(name, _) if dead_outputs.contains_key(name) => Rc::new(Expr::Get(
+                    call.clone(),
+                    shift_index(*index, &dead_outputs[name]),
+                )),
+                _ => expr,
+            },
+            _ => expr,
+        });
+        true
+    }
+}
+
+#[test]
+fn test_remove_dead_functions_and_args() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    let prog = program!(
+        function(
+            "main",
+            base(intt()),
+            base(intt()),
+            get(call("helper", parallel!(int(5), arg(), int(7))), 1)
+        ),
+        function(
+            "helper",
+            tuplet!(intt(), intt(), intt()),
+            tuplet!(intt(), intt()),
+            parallel!(getat(0), add(getat(1), int(1)))
+        ),
+        function("unused", base(intt()), base(intt()), add(arg(), int(1))),
+    );
+    let optimized = prog.remove_dead_functions();
+
+    assert_eq!(optimized.fns(), vec!["main", "helper"]);
+    // the first output was unused, which made the first argument unused
+    let helper = optimized.get_function("helper").unwrap();
+    assert_eq!(helper.func_input_ty().unwrap(), tuplet!(intt()));
+    assert_eq!(helper.func_output_ty().unwrap(), tuplet!(intt()));
+    assert_eq!(
+        interpret_dag_prog(&prog, &intv(3)),
+        interpret_dag_prog(&optimized, &intv(3))
+    );
+}
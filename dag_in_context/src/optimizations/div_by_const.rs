@@ -0,0 +1,183 @@
+//! Magic numbers for division by constants, used by `div_by_const.egg`.
+//! Finding them needs 128-bit arithmetic, which egglog doesn't have,
+//! so we compute them here for every constant divisor in the program.
+
+use std::{fmt::Write, rc::Rc};
+
+use indexmap::IndexSet;
+
+use crate::schema::{BinaryOp, Constant, Expr, TreeProgram};
+
+/// The magic number and shift for signed division by `d`,
+/// following Hacker's Delight, figure 10-1.
+/// `n / d` is `mulhi(m, n) >> s` (adding `n` to the product when `m` is negative),
+/// plus one when `n` is negative.
+fn magic(d: i64) -> (i64, u32) {
+    assert!(
+        d >= 3 && d.count_ones() > 1,
+        "expected a divisor that isn't a power of two"
+    );
+    let two63: u64 = 1 << 63;
+    let ad = d as u64;
+    // the largest value of |n| with n rem d = d - 1
+    let anc = two63 - 1 - two63 % ad;
+    let mut p = 63;
+    let (mut q1, mut r1) = (two63 / anc, two63 % anc);
+    let (mut q2, mut r2) = (two63 / ad, two63 % ad);
+    loop {
+        p += 1;
+        q1 = q1.wrapping_mul(2);
+        r1 = r1.wrapping_mul(2);
+        if r1 >= anc {
+            q1 = q1.wrapping_add(1);
+            r1 = r1.wrapping_sub(anc);
+        }
+        q2 = q2.wrapping_mul(2);
+        r2 = r2.wrapping_mul(2);
+        if r2 >= ad {
+            q2 = q2.wrapping_add(1);
+            r2 = r2.wrapping_sub(ad);
+        }
+        let delta = ad - r2;
+        if !(q1 < delta || (q1 == delta && r1 == 0)) {
+            break;
+        }
+    }
+    (q2.wrapping_add(1) as i64, p - 64)
+}
+
+/// Egglog `DivMagic` facts for the constant divisors in the program.
+/// Negative divisors are rewritten to positive ones, so they get facts too.
+pub(crate) fn div_magic_facts(program: &TreeProgram) -> String {
+    let mut seen = IndexSet::new();
+    let mut divisors = IndexSet::new();
+    let mut todo: Vec<_> = program
+        .fns()
+        .iter()
+        .map(|name| program.get_function(name).unwrap().clone())
+        .collect();
+    while let Some(expr) = todo.pop() {
+        if !seen.insert(Rc::as_ptr(&expr)) {
+            continue;
+        }
+        if let Expr::Bop(BinaryOp::Div, _, rhs) = expr.as_ref() {
+            if let Expr::Const(Constant::Int(d), _, _) = rhs.as_ref() {
+                let d = d.unsigned_abs();
+                if d >= 3 && d.count_ones() > 1 && d <= i64::MAX as u64 {
+                    divisors.insert(d as i64);
+                }
+            }
+        }
+        todo.extend(expr.children_exprs());
+    }
+
+    let mut res = String::new();
+    for d in divisors {
+        let (m, s) = magic(d);
+        writeln!(res, "(DivMagic {d} {m} {s})").unwrap();
+    }
+    res
+}
+
+#[test]
+fn test_magic_numbers() {
+    let mulhi = |a: i64, b: i64| ((a as i128 * b as i128) >> 64) as i64;
+    let divide = |n: i64, d: i64| {
+        let (m, s) = magic(d);
+        let mut q = mulhi(m, n);
+        if m < 0 {
+            q = q.wrapping_add(n);
+        }
+        (q >> s) - (n >> 63)
+    };
+    for d in [3, 5, 6, 7, 10, 641, 1_000_000_007, (1 << 40) + 3, i64::MAX] {
+        for n in [
+            0,
+            1,
+            -1,
+            d - 1,
+            d,
+            1 - d,
+            -d,
+            i64::MAX,
+            i64::MIN,
+            i64::MIN + 1,
+            123_456_789_012_345,
+            -987_654_321,
+        ] {
+            assert_eq!(divide(n, d), n.wrapping_div(d), "{n} / {d}");
+        }
+    }
+}
+
+#[test]
+fn test_div_by_const() -> crate::Result {
+    use crate::ast::*;
+    use crate::egglog_test;
+    // x / 7 => (mulhi(m, x) >> 1) - (x >> 63), where m fits in an i64
+    let prog = function("main", base(intt()), base(intt()), div(arg(), int(7)))
+        .func_with_arg_types()
+        .func_to_program();
+    assert_eq!(magic(7), (5270498306774157605, 1));
+    let ty = |e: crate::schema::RcExpr| e.with_arg_types(base(intt()), base(intt()));
+    let div7 = ty(div(arg(), int(7)));
+    let magic_div = ty(sub(
+        shr(mulhi(int(5270498306774157605), arg()), int(1)),
+        shr(arg(), int(63)),
+    ));
+    egglog_test(
+        &format!("{}\n(let div7 {div7})", div_magic_facts(&prog)),
+        &format!("(check (= div7 {magic_div}))"),
+        vec![prog],
+        intv(-100),
+        intv(-14),
+        vec![],
+    )
+}
+
+#[test]
+fn test_mul_by_const() -> crate::Result {
+    use crate::ast::*;
+    use crate::egglog_test;
+    // x * 9 => (x << 3) + x, x * 7 => (x << 3) - x
+    let ty = |e: crate::schema::RcExpr| e.with_arg_types(base(intt()), base(intt()));
+    let nine = ty(mul(arg(), int(9)));
+    let seven = ty(mul(arg(), int(7)));
+    egglog_test(
+        &format!("(let nine {nine}) (let seven {seven})"),
+        &format!(
+            "(check (= nine {}))\n(check (= seven {}))",
+            ty(add(shl(arg(), int(3)), arg())),
+            ty(sub(shl(arg(), int(3)), arg())),
+        ),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
+
+#[test]
+fn test_div_by_const_negative_magic() -> crate::Result {
+    use crate::ast::*;
+    use crate::egglog_test;
+    // x / 15 => ((mulhi(m, x) + x) >> 3) - (x >> 63), where m wrapped around
+    let prog = function("main", base(intt()), base(intt()), div(arg(), int(15)))
+        .func_with_arg_types()
+        .func_to_program();
+    assert_eq!(magic(15), (-8608480567731124087, 3));
+    let ty = |e: crate::schema::RcExpr| e.with_arg_types(base(intt()), base(intt()));
+    let div15 = ty(div(arg(), int(15)));
+    let magic_div = ty(sub(
+        shr(add(mulhi(int(-8608480567731124087), arg()), arg()), int(3)),
+        shr(arg(), int(63)),
+    ));
+    egglog_test(
+        &format!("{}\n(let div15 {div15})", div_magic_facts(&prog)),
+        &format!("(check (= div15 {magic_div}))"),
+        vec![prog],
+        intv(-100),
+        intv(-6),
+        vec![],
+    )
+}
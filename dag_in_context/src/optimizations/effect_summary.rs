@@ -0,0 +1,209 @@
+//! Interprocedural effect summaries.
+//! Every `Call` threads the state edge, so without summaries the egraph
+//! has to treat calls as arbitrary effects.
+//! Summaries are computed over the call graph (callees first) and
+//! given to the egraph as facts used by `purity_analysis.egg`.
+
+use std::{fmt::Write, rc::Rc};
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    optimizations::call_graph::CallGraph,
+    schema::{BaseType, BinaryOp, Expr, RcExpr, TernaryOp, TreeProgram, Type},
+};
+
+/// What a function may do to memory, from least to most effectful.
+/// Summaries of callers are the maximum over their operations and callees.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Effect {
+    /// Doesn't touch memory or print. The output state is the input state.
+    Pure,
+    /// Only loads from memory.
+    ReadOnly,
+    /// Loads and writes, but doesn't allocate, free or print.
+    /// Any pointer it writes to must have come from its arguments.
+    WritesArgs,
+    /// Anything else, including calls to unknown functions.
+    Unknown,
+}
+
+/// The effect of the operations in `body`.
+/// Calls use the current summaries of their callees.
+fn body_effect(body: &RcExpr, effects: &IndexMap<String, Effect>) -> Effect {
+    let mut seen = IndexSet::new();
+    let mut todo = vec![body.clone()];
+    let mut res = Effect::Pure;
+    while let Some(expr) = todo.pop() {
+        if !seen.insert(Rc::as_ptr(&expr)) {
+            continue;
+        }
+        let effect = match expr.as_ref() {
+            Expr::Bop(BinaryOp::Load, _, _) => Effect::ReadOnly,
+            Expr::Top(TernaryOp::Write, _, _, _) => Effect::WritesArgs,
+            Expr::Bop(BinaryOp::Print | BinaryOp::Free, _, _) | Expr::Alloc(..) => Effect::Unknown,
+            Expr::Call(name, _) => effects.get(name).copied().unwrap_or(Effect::Unknown),
+            _ => Effect::Pure,
+        };
+        res = res.max(effect);
+        todo.extend(expr.children_exprs());
+    }
+    res
+}
+
+/// Computes an effect summary for every function in the program.
+pub(crate) fn function_effects(program: &TreeProgram) -> IndexMap<String, Effect> {
+    let call_graph = CallGraph::new(program);
+    let mut effects = IndexMap::new();
+    for scc in call_graph.sccs() {
+        // optimistically assume the component is pure, then iterate to a fixed point
+        for func in scc {
+            let effect = match program.get_function(func) {
+                Some(_) => Effect::Pure,
+                None => Effect::Unknown,
+            };
+            effects.insert(func.clone(), effect);
+        }
+        loop {
+            let mut changed = false;
+            for func in scc {
+                let Some(body) = program.get_function(func).and_then(|f| f.func_body()) else {
+                    continue;
+                };
+                let effect = body_effect(body, &effects);
+                if effect > effects[func] {
+                    effects.insert(func.clone(), effect);
+                    changed = true;
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+    effects
+}
+
+/// Egglog facts for the effect summaries, used by `purity_analysis.egg`.
+pub(crate) fn effect_facts(program: &TreeProgram) -> String {
+    let mut res = String::new();
+    for (func, effect) in function_effects(program) {
+        let relation = match effect {
+            Effect::Pure => "FunctionIsPure",
+            Effect::ReadOnly => "FunctionIsReadOnly",
+            // no rule distinguishes functions that write from arbitrary ones yet
+            Effect::WritesArgs | Effect::Unknown => continue,
+        };
+        writeln!(res, "({relation} \"{func}\")").unwrap();
+
+        // functions that don't write leave the state edge unchanged
+        if effect <= Effect::ReadOnly {
+            let func_expr = program.get_function(&func).unwrap();
+            let state_index = |ty: Type| match ty {
+                Type::TupleT(tys) => tys.iter().position(|ty| *ty == BaseType::StateT),
+                _ => None,
+            };
+            if let (Some(input), Some(output)) = (
+                state_index(func_expr.func_input_ty().unwrap()),
+                state_index(func_expr.func_output_ty().unwrap()),
+            ) {
+                writeln!(
+                    res,
+                    "(FunctionStatePassthrough \"{func}\" {input} {output})"
+                )
+                .unwrap();
+            }
+        }
+    }
+    res
+}
+
+#[test]
+fn test_function_effects() {
+    use crate::ast::*;
+    let prog = program!(
+        function(
+            "main",
+            tuplet!(pointert(intt()), statet()),
+            base(statet()),
+            get(call("writer", arg()), 0)
+        ),
+        function(
+            "writer",
+            tuplet!(pointert(intt()), statet()),
+            tuplet!(statet()),
+            single(write(
+                getat(0),
+                get(call("reader", arg()), 0),
+                get(call("reader", arg()), 1)
+            ))
+        ),
+        function(
+            "reader",
+            tuplet!(pointert(intt()), statet()),
+            tuplet!(intt(), statet()),
+            parallel!(
+                get(call("square", parallel!(int(3), getat(1))), 0),
+                get(load(getat(0), getat(1)), 1)
+            )
+        ),
+        function(
+            "square",
+            tuplet!(intt(), statet()),
+            tuplet!(intt(), statet()),
+            parallel!(mul(getat(0), getat(0)), getat(1))
+        ),
+    );
+    let effects = function_effects(&prog);
+    assert_eq!(effects["square"], Effect::Pure);
+    assert_eq!(effects["reader"], Effect::ReadOnly);
+    assert_eq!(effects["writer"], Effect::WritesArgs);
+    assert_eq!(effects["main"], Effect::WritesArgs);
+}
+
+#[test]
+fn test_redundant_pure_call() -> crate::Result {
+    use crate::ast::*;
+    // square is called twice with the same argument, so the second
+    // call's result is the first's even though its state differs
+    let first = call("square", parallel!(int(3), getat(0)));
+    let second = call("square", parallel!(int(3), get(first.clone(), 1)));
+    let prog = program!(
+        function(
+            "main",
+            tuplet!(statet()),
+            tuplet!(intt(), statet()),
+            parallel!(
+                add(get(first.clone(), 0), get(second.clone(), 0)),
+                get(second.clone(), 1)
+            )
+        ),
+        function(
+            "square",
+            tuplet!(intt(), statet()),
+            tuplet!(intt(), statet()),
+            parallel!(mul(getat(0), getat(0)), getat(1))
+        ),
+    );
+    let (with_context, cache) = prog.add_context();
+    let (first, first_cache) = first
+        .with_arg_types(tuplet!(statet()), tuplet!(intt(), statet()))
+        .add_ctx(infunc("main"));
+    crate::egglog_test(
+        &format!(
+            "{with_context}\n{}\n{}",
+            cache.get_unions(),
+            effect_facts(&prog)
+        ),
+        &format!(
+            "(let first {first})\n{}
+(check (= (Get first 0) (Get (Call \"square\" args2) 0))
+       (= args2 (Concat (Single c) (Single (Get first 1)))))",
+            first_cache.get_unions()
+        ),
+        vec![with_context],
+        tuplev!(statev()),
+        tuplev!(intv(18), statev()),
+        vec![],
+    )
+}
@@ -0,0 +1,79 @@
+//! Tests for the fast-math ruleset
+#![cfg(test)]
+
+use crate::{egglog_test, Result};
+
+#[test]
+fn fast_math_rewrites() -> Result {
+    use crate::ast::*;
+    // x / 4.0  => x * 0.25
+    // x * 2.0  => x + x
+    // (x + 1.5) + 2.5 => x + 4.0
+    // x * y + x * 3.0 => x * (y + 3.0)
+    let ty =
+        |e: crate::schema::RcExpr| e.with_arg_types(tuplet!(floatt(), floatt()), base(floatt()));
+    let (x, y) = (getat(0), getat(1));
+    let div = ty(fdiv(x.clone(), float(4.0)));
+    let double = ty(fmul(x.clone(), float(2.0)));
+    let sum = ty(fadd(fadd(x.clone(), float(1.5)), float(2.5)));
+    let factored = ty(fadd(
+        fmul(x.clone(), y.clone()),
+        fmul(x.clone(), float(3.0)),
+    ));
+
+    egglog_test(
+        &format!("(let div {div}) (let double {double}) (let sum {sum}) (let factored {factored})"),
+        &format!(
+            "(run-schedule (repeat 3 fast-math))
+             (check (= div {}))
+             (check (= double {}))
+             (check (= sum {}))
+             (check (= factored {}))",
+            ty(fmul(x.clone(), float(0.25))),
+            ty(fadd(x.clone(), x.clone())),
+            ty(fadd(x.clone(), float(4.0))),
+            ty(fmul(x.clone(), fadd(y, float(3.0)))),
+        ),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
+
+#[test]
+fn no_fast_math_by_default() -> Result {
+    use crate::ast::*;
+    // without fast-math, (x + 1.5) + 2.5 is not reassociated
+    let ty = |e: crate::schema::RcExpr| e.with_arg_types(tuplet!(floatt()), base(floatt()));
+    let sum = ty(fadd(fadd(getat(0), float(1.5)), float(2.5)));
+    egglog_test(
+        &format!("(let sum {sum})"),
+        &format!("(fail (check (= sum {})))", ty(fadd(getat(0), float(4.0)))),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
+
+#[test]
+fn fast_math_in_both_schedules() {
+    use crate::schedule::{mk_sequential_schedule, parallel_schedule};
+    use crate::EggccConfig;
+    let uses_fast_math = |passes: Vec<crate::schedule::CompilerPass>| {
+        passes
+            .iter()
+            .any(|pass| pass.egglog_schedule().contains("fast-math"))
+    };
+    let config = EggccConfig {
+        fast_math: true,
+        ..EggccConfig::default()
+    };
+    assert!(uses_fast_math(parallel_schedule(&config)));
+    assert!(uses_fast_math(mk_sequential_schedule(&config)));
+    assert!(!uses_fast_math(parallel_schedule(&EggccConfig::default())));
+    assert!(!uses_fast_math(mk_sequential_schedule(
+        &EggccConfig::default()
+    )));
+}
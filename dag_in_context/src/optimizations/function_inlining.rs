@@ -2,9 +2,14 @@ use std::rc::Rc;
 
 use indexmap::IndexMap;
 
-use crate::schema::{Expr, RcExpr, TreeProgram};
+use crate::{
+    config::InlinePolicy,
+    optimizations::call_graph::CallGraph,
+    schema::{Expr, RcExpr, TreeProgram},
+};
 
-fn subst_expr(arg: &RcExpr, within: &RcExpr) -> RcExpr {
+/// Substitutes `arg` for the argument of `within`, leaving nested regions alone.
+pub(crate) fn subst_expr(arg: &RcExpr, within: &RcExpr) -> RcExpr {
     let mut cache = IndexMap::new();
     subst_expr_with_cache(arg, within, &mut cache)
 }
@@ -86,10 +91,11 @@ fn inline_once_in_expr(
         Call(name, args) => {
             let args_inlined = inline_once_in_expr(args, func_name_to_body, inlined_cache);
 
+            // only callees chosen by the inlining policy are in the map
             if let Some(body) = func_name_to_body.get(name) {
                 subst_expr(&args_inlined, body)
             } else {
-                panic!("Function {name} not found for inlining");
+                Rc::new(Call(name.clone(), args_inlined))
             }
         }
         Top(op, x, y, z) => Rc::new(Top(
@@ -155,33 +161,87 @@ fn inline_once_in_expr(
     result
 }
 
-#[allow(dead_code)]
-fn build_func_body_map(program: &TreeProgram) -> IndexMap<String, RcExpr> {
+/// Computes the tree size of an expression, matching `Expr-size` in `expr_size.egg`.
+/// Shared subexpressions are counted once per use, but each is only visited once.
+pub(crate) fn tree_size(expr: &RcExpr) -> usize {
+    let mut cache = IndexMap::new();
+    tree_size_with_cache(expr, &mut cache)
+}
+
+fn tree_size_with_cache(expr: &RcExpr, cache: &mut IndexMap<*const Expr, usize>) -> usize {
+    if let Some(size) = cache.get(&Rc::as_ptr(expr)) {
+        return *size;
+    }
+    let children_size = expr.children_exprs().iter().fold(0usize, |acc, child| {
+        acc.saturating_add(tree_size_with_cache(child, cache))
+    });
+    use Expr::*;
+    let size = match expr.as_ref() {
+        Const(..) | Arg(..) => 1,
+        Empty(..) | Symbolic(..) => 0,
+        Get(..) | Single(..) | Concat(..) => children_size,
+        // the state edge of an alloc is not counted
+        Alloc(_, amount, _, _) => tree_size_with_cache(amount, cache).saturating_add(1),
+        Top(..) | Bop(..) | Uop(..) | Call(..) | If(..) | Switch(..) | DoWhile(..)
+        | Function(..) => children_size.saturating_add(1),
+    };
+    cache.insert(Rc::as_ptr(expr), size);
+    size
+}
+
+/// Chooses which functions to inline at their call sites, using the call graph
+/// and the size thresholds in `policy`.
+/// Recursive functions are never inlined, since that just unrolls the recursion.
+fn functions_to_inline(program: &TreeProgram, policy: &InlinePolicy) -> Vec<String> {
+    let call_graph = CallGraph::new(program);
+    program
+        .fns()
+        .into_iter()
+        .filter(|name| {
+            if call_graph.is_recursive(name) || call_graph.num_call_sites(name) == 0 {
+                return false;
+            }
+            let body = program.get_function(name).unwrap().func_body().unwrap();
+            let size = tree_size(body);
+            size <= policy.max_callee_size
+                || (call_graph.is_leaf(name) && size <= policy.max_leaf_size)
+                || (policy.inline_single_call_site && call_graph.num_call_sites(name) == 1)
+        })
+        .collect()
+}
+
+fn build_func_body_map(program: &TreeProgram, to_inline: &[String]) -> IndexMap<String, RcExpr> {
     let mut map: IndexMap<String, RcExpr> = IndexMap::new();
-    let mut push_fn = |f: &RcExpr| {
-        let name = f
-            .func_name()
-            .expect("Function should have name for inlining");
+    for name in to_inline {
+        let f = program
+            .get_function(name)
+            .expect("Function should exist for inlining");
         let body = f.func_body().expect("Function should have body").clone();
-        map.insert(name, body);
-    };
-    push_fn(&program.entry);
-    for f in &program.functions {
-        push_fn(f);
+        map.insert(name.clone(), body);
     }
     map
 }
 
-pub fn perform_inlining(program: &TreeProgram, fns: Vec<String>, iterations: usize) -> TreeProgram {
-    if iterations == 0 || fns.is_empty() {
+/// Inlines calls in the functions `fns`, choosing callees according to `policy`.
+pub fn perform_inlining(
+    program: &TreeProgram,
+    fns: Vec<String>,
+    policy: &InlinePolicy,
+) -> TreeProgram {
+    if policy.iterations == 0 || fns.is_empty() {
         return program.clone();
     }
-    let func_name_to_body = build_func_body_map(program);
+    let to_inline = functions_to_inline(program, policy);
+    if to_inline.is_empty() {
+        return program.clone();
+    }
+    log::info!("Inlining calls to {:?}", to_inline);
+    let func_name_to_body = build_func_body_map(program, &to_inline);
     let rewrite_fn = |func: &RcExpr| {
         let name = func.func_name().unwrap();
         let mut body = func.func_body().unwrap().clone();
         if fns.contains(&name) {
-            for _ in 0..iterations {
+            for _ in 0..policy.iterations {
                 let mut cache = IndexMap::new();
                 body = inline_once_in_expr(&body, &func_name_to_body, &mut cache);
             }
@@ -200,3 +260,72 @@ pub fn perform_inlining(program: &TreeProgram, fns: Vec<String>, iterations: usi
     let res_untyped = TreeProgram { entry, functions };
     res_untyped.override_arg_types()
 }
+
+#[cfg(test)]
+fn count_calls(program: &TreeProgram, func: &str) -> usize {
+    let body = program.get_function(func).unwrap().func_body().unwrap();
+    crate::optimizations::call_graph::calls_in_expr(body).len()
+}
+
+#[test]
+fn test_inline_small_not_recursive() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    let prog = program!(
+        function(
+            "main",
+            base(intt()),
+            base(intt()),
+            add(call("inc", arg()), call("fact", arg()))
+        ),
+        function("inc", base(intt()), base(intt()), add(arg(), int(1))),
+        function(
+            "fact",
+            base(intt()),
+            base(intt()),
+            tif(
+                less_than(arg(), int(2)),
+                arg(),
+                int(1),
+                mul(arg(), call("fact", sub(arg(), int(1))))
+            )
+        ),
+    );
+    let inlined = perform_inlining(&prog, vec!["main".to_string()], &InlinePolicy::default());
+
+    // inc is small and inlined, fact is recursive and stays a call
+    assert_eq!(count_calls(&inlined, "main"), 1);
+    assert_eq!(count_calls(&inlined, "fact"), 1);
+    assert_eq!(
+        interpret_dag_prog(&prog, &intv(4)),
+        interpret_dag_prog(&inlined, &intv(4))
+    );
+}
+
+#[test]
+fn test_inline_respects_size_threshold() {
+    use crate::ast::*;
+    let mut big_body = arg();
+    for i in 0..20 {
+        big_body = mul(add(big_body, int(i)), int(2));
+    }
+    let prog = program!(
+        function(
+            "main",
+            base(intt()),
+            base(intt()),
+            add(call("big", arg()), call("big", int(3)))
+        ),
+        function("big", base(intt()), base(intt()), big_body),
+    );
+    let policy = InlinePolicy {
+        max_callee_size: 10,
+        max_leaf_size: 10,
+        ..InlinePolicy::default()
+    };
+    let not_inlined = perform_inlining(&prog, vec!["main".to_string()], &policy);
+    assert_eq!(count_calls(&not_inlined, "main"), 2);
+
+    let inlined = perform_inlining(&prog, vec!["main".to_string()], &InlinePolicy::all());
+    assert_eq!(count_calls(&inlined, "main"), 0);
+}
@@ -0,0 +1,201 @@
+use crate::schema_helpers::{Constructor, ESort, Purpose};
+use std::iter;
+use strum::IntoEnumIterator;
+
+#[cfg(test)]
+use crate::egglog_test;
+
+/// Builds the base cases of `ArgMask`: constants use no arguments
+/// and the whole argument may use any of them.
+fn arg_mask_base_case_for_ctor(ctor: Constructor) -> Option<String> {
+    let ruleset = " :ruleset always-run";
+    let ctor_pattern = ctor.construct(|field| field.var());
+    match ctor {
+        Constructor::Const | Constructor::Empty => Some(format!(
+            "
+(rule ((BodyContainsExpr body expr)
+       (DoWhile in body)
+       (= expr {ctor_pattern}))
+      ((set (ArgMask body expr) 0)){ruleset})"
+        )),
+        // the whole argument may use any index
+        Constructor::Arg => Some(format!(
+            "
+(rule ((BodyContainsExpr body expr)
+       (DoWhile in body)
+       (= expr {ctor_pattern}))
+      ((set (ArgMask body expr) -1)){ruleset})"
+        )),
+        _ => None,
+    }
+}
+
+/// Builds rules like:
+/// ```txt
+/// (rule ((BodyContainsExpr body expr)
+///        (DoWhile in body)
+///        (= expr (Bop _op _x _y))
+///        (= _x-mask (ArgMask body _x))
+///        (= _y-mask (ArgMask body _y)))
+///       ((set (ArgMask body expr) (| _x-mask _y-mask)))
+///       :ruleset always-run)
+/// ```
+/// Captured regions have their own arguments, so only sub-expressions are considered.
+fn arg_mask_rule_for_ctor(ctor: Constructor) -> Option<String> {
+    let ruleset = " :ruleset always-run";
+    if ctor.sort() != ESort::Expr {
+        return None;
+    }
+    let masks = ctor.filter_map_fields(|field| {
+        (field.purpose == Purpose::SubExpr).then(|| format!("{}-mask", field.var()))
+    });
+    if masks.is_empty() {
+        return None;
+    }
+    let ctor_pattern = ctor.construct(|field| field.var());
+    let queries = ctor
+        .filter_map_fields(|field| {
+            (field.purpose == Purpose::SubExpr)
+                .then(|| format!("(= {var}-mask (ArgMask body {var}))", var = field.var()))
+        })
+        .join("\n       ");
+    let mask = masks
+        .iter()
+        .skip(1)
+        .fold(masks[0].clone(), |acc, mask| format!("(| {acc} {mask})"));
+    Some(format!(
+        "
+(rule ((BodyContainsExpr body expr)
+       (DoWhile in body)
+       (= expr {ctor_pattern})
+       {queries})
+      ((set (ArgMask body expr) {mask})){ruleset})"
+    ))
+}
+
+pub(crate) fn rules() -> Vec<String> {
+    iter::once(include_str!("loop_fusion.egg").to_string())
+        .chain(Constructor::iter().filter_map(arg_mask_base_case_for_ctor))
+        .chain(Constructor::iter().filter_map(arg_mask_rule_for_ctor))
+        .collect::<Vec<_>>()
+}
+
+#[test]
+fn loop_fusion_same_inputs() -> crate::Result {
+    use crate::ast::*;
+    let inputs = parallel!(int(0), int(1));
+    let next = add(getat(0), int(1));
+    let pred = less_than(next.clone(), int(4));
+    let sum = dowhile(
+        inputs.clone(),
+        parallel!(pred.clone(), next.clone(), add(getat(1), getat(0))),
+    );
+    let doubled = dowhile(inputs, parallel!(pred, next, mul(getat(1), int(2))));
+    let prog = parallel!(get(sum, 1), get(doubled, 1)).add_arg_type(base(intt()));
+
+    // both loops run 4 times, so they are fused,
+    // with the sum's values first and the product's after them
+    egglog_test(
+        &format!("{prog}"),
+        &format!(
+            "
+(let prog {prog})
+(check (= fused (DoWhile (Concat inputs inputs) fused-body))
+       (= prog (Concat (Single (Get fused 1)) (Single (Get fused 3))))
+       (= (LoopGeneration (Concat inputs inputs) fused-body) 1))"
+        ),
+        vec![prog.to_program(base(intt()), tuplet!(intt(), intt()))],
+        intv(0),
+        tuplev!(intv(7), intv(16)),
+        vec![],
+    )
+}
+
+#[test]
+fn loop_fusion_disjoint_memory() -> crate::Result {
+    use crate::ast::*;
+    // a[i] = i for i in 0..4, then b[i] = 2 * i for i in 0..4
+    let a = alloc(0, int(4), getat(0), pointert(intt()));
+    let b = alloc(1, int(4), get(a.clone(), 1), pointert(intt()));
+    let next = add(getat(0), int(1));
+    let pred = less_than(next.clone(), int(4));
+    let fill_a = dowhile(
+        parallel!(
+            int(0),
+            get(a.clone(), 0),
+            get(b.clone(), 0),
+            get(b.clone(), 1)
+        ),
+        parallel!(
+            pred.clone(),
+            next.clone(),
+            getat(1),
+            getat(2),
+            write(ptradd(getat(1), getat(0)), getat(0), getat(3))
+        ),
+    );
+    let fill_b = dowhile(
+        parallel!(int(0), get(a.clone(), 0), get(b.clone(), 0), get(fill_a, 3)),
+        parallel!(
+            pred,
+            next,
+            getat(1),
+            getat(2),
+            write(ptradd(getat(2), getat(0)), mul(getat(0), int(2)), getat(3))
+        ),
+    );
+    let last_a = load(ptradd(get(a.clone(), 0), int(3)), get(fill_b, 3));
+    let last_b = load(ptradd(get(b.clone(), 0), int(3)), get(last_a.clone(), 1));
+    let state = free(get(a, 0), get(last_b.clone(), 1));
+    let state = free(get(b, 0), state);
+    let prog = program!(function(
+        "main",
+        tuplet!(statet()),
+        tuplet!(intt(), statet()),
+        parallel!(add(get(last_a, 0), get(last_b, 0)), state)
+    ),);
+    let (with_context, cache) = prog.add_context();
+
+    // the loops write different allocations, so they are fused
+    // and the second loop's state comes from the fused loop
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "
+(check (MemoryFused fill-a fused 3)
+       (= fill-b (DoWhile inputs-b body-b))
+       (= (Get inputs-b 3) (Get fill-a 3))
+       (= (Get fill-b 3) (Get fused 3)))",
+        vec![with_context],
+        tuplev!(statev()),
+        tuplev!(intv(9), statev()),
+        vec![],
+    )
+}
+
+#[test]
+fn loop_fission_independent_values() -> crate::Result {
+    use crate::ast::*;
+    let next = add(getat(0), int(1));
+    let looped = dowhile(
+        parallel!(int(0), int(1), int(1)),
+        parallel!(
+            less_than(next.clone(), int(4)),
+            next,
+            add(getat(1), getat(0)),
+            mul(getat(2), int(2))
+        ),
+    );
+    let prog = parallel!(get(looped.clone(), 1), get(looped, 2)).add_arg_type(base(intt()));
+
+    // the sum and the product only share the counter,
+    // so the loop is split into one for each of them
+    egglog_test(
+        &format!("{prog}"),
+        "(check (LoopRestricted looped sum-loop 3))
+         (check (LoopRestricted looped product-loop 5))",
+        vec![prog.to_program(base(intt()), tuplet!(intt(), intt()))],
+        intv(0),
+        tuplev!(intv(7), intv(16)),
+        vec![],
+    )
+}
@@ -0,0 +1,662 @@
+//! Interchanges perfectly nested loops that walk arrays in a cache-unfriendly order.
+//!
+//! A nest is two `DoWhile`s with unit-stride counters `i` (outer) and `j` (inner),
+//! where the outer body only runs the inner loop, and the inner body only
+//! loads and writes through pointers passed into the nest.
+//! Addresses are `PtrAdd` chains whose offsets are polynomials of
+//! degree at most two in the inner loop's argument, such as `i * n + j`.
+//!
+//! The interchange is legal when every array written in the nest is indexed
+//! injectively in `(i, j)` and is only read at the index it is written at:
+//! then all dependence distances are `(0, 0)` and any order of the iterations works.
+//! The interchanged nest is unioned with the original one when building the e-graph,
+//! so the extractor picks the order.
+//! For both inner loops, `LoopStridedAccesses` records how many accesses don't have
+//! unit stride, and the extractor charges for them on every iteration.
+//! Tiling is not done.
+
+use std::{collections::BTreeMap, fmt::Write, rc::Rc};
+
+use egglog::Term;
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    add_context::ContextCache,
+    ast::{add, dowhile, get, getat, int, less_than, parallel_vec, smax},
+    print_with_intermediate_helper,
+    remove_dead_code_nodes::try_split_inputs,
+    schema::{BaseType, BinaryOp, Constant, Expr, RcExpr, TernaryOp, TreeProgram, Type},
+    to_egglog::TreeToEgglog,
+};
+
+/// A polynomial over the loop argument.
+/// Each monomial is a sorted list of argument indices, mapped to its coefficient.
+type Poly = BTreeMap<Vec<usize>, i64>;
+
+fn poly_add(a: &Poly, b: &Poly, sign: i64) -> Option<Poly> {
+    let mut res = a.clone();
+    for (mono, coeff) in b {
+        let entry = res.entry(mono.clone()).or_insert(0);
+        *entry = entry.checked_add(sign.checked_mul(*coeff)?)?;
+    }
+    res.retain(|_, coeff| *coeff != 0);
+    Some(res)
+}
+
+fn poly_mul(a: &Poly, b: &Poly) -> Option<Poly> {
+    let mut res = Poly::new();
+    for (mono_a, coeff_a) in a {
+        for (mono_b, coeff_b) in b {
+            let mut mono = [mono_a.as_slice(), mono_b.as_slice()].concat();
+            if mono.len() > 2 {
+                return None;
+            }
+            mono.sort();
+            *res.entry(mono).or_insert(0) += coeff_a.checked_mul(*coeff_b)?;
+        }
+    }
+    res.retain(|_, coeff| *coeff != 0);
+    Some(res)
+}
+
+/// The integer expression as a polynomial in the loop argument, if it is one.
+fn as_poly(expr: &RcExpr) -> Option<Poly> {
+    match expr.as_ref() {
+        Expr::Const(Constant::Int(c), _, _) => Some(Poly::from([(vec![], *c)])),
+        Expr::Get(_, i) if arg_index(expr).is_some() => Some(Poly::from([(vec![*i], 1)])),
+        Expr::Bop(BinaryOp::Add, a, b) => poly_add(&as_poly(a)?, &as_poly(b)?, 1),
+        Expr::Bop(BinaryOp::Sub, a, b) => poly_add(&as_poly(a)?, &as_poly(b)?, -1),
+        Expr::Bop(BinaryOp::Mul, a, b) => poly_mul(&as_poly(a)?, &as_poly(b)?),
+        _ => None,
+    }
+}
+
+/// Returns `i` when the expression is `(Get (Arg ..) i)`.
+fn arg_index(expr: &RcExpr) -> Option<usize> {
+    match expr.as_ref() {
+        Expr::Get(arg, i) if matches!(arg.as_ref(), Expr::Arg(..)) => Some(*i),
+        _ => None,
+    }
+}
+
+/// Whether the loop passes value `i` through unchanged.
+fn is_passthrough(outputs: &[RcExpr], i: usize) -> bool {
+    arg_index(&outputs[i + 1]) == Some(i)
+}
+
+/// Finds a counter `c` and a passed-through bound `b` in a loop's outputs
+/// (predicate first) of the form
+/// `c' = c + 1` and `pred = c' < b`.
+/// Also returns the type of the loop argument.
+fn unit_counter(outputs: &[RcExpr]) -> Option<(usize, usize, Type)> {
+    let Expr::Bop(BinaryOp::LessThan, next, bound) = outputs[0].as_ref() else {
+        return None;
+    };
+    let Expr::Bop(BinaryOp::Add, counter, one) = next.as_ref() else {
+        return None;
+    };
+    let (c, b) = (arg_index(counter)?, arg_index(bound)?);
+    let Expr::Get(arg, _) = counter.as_ref() else {
+        unreachable!()
+    };
+    let Expr::Arg(ty, _) = arg.as_ref() else {
+        unreachable!()
+    };
+    let is_next = outputs.get(c + 1)?.as_ref() == next.as_ref();
+    let unit = matches!(one.as_ref(), Expr::Const(Constant::Int(1), _, _));
+    (is_next && unit && outputs.len() > b + 1 && is_passthrough(outputs, b))
+        .then(|| (c, b, ty.clone()))
+}
+
+/// A load or a write in the inner loop.
+struct Access {
+    /// The index of the base pointer in the inner loop's argument.
+    base: usize,
+    index: Poly,
+    is_write: bool,
+}
+
+/// The base pointer and offset of an address.
+fn address(expr: &RcExpr) -> Option<(usize, Poly)> {
+    match expr.as_ref() {
+        Expr::Bop(BinaryOp::PtrAdd, ptr, offset) => {
+            let (base, index) = address(ptr)?;
+            Some((base, poly_add(&index, &as_poly(offset)?, 1)?))
+        }
+        _ => Some((arg_index(expr)?, Poly::new())),
+    }
+}
+
+/// The memory accesses in a loop body, or `None` if the body
+/// has other effects, regions or addresses we don't understand.
+fn accesses(body: &RcExpr) -> Option<Vec<Access>> {
+    let mut seen = IndexSet::new();
+    let mut res = vec![];
+    let mut todo = vec![body.clone()];
+    while let Some(expr) = todo.pop() {
+        if !seen.insert(Rc::as_ptr(&expr)) {
+            continue;
+        }
+        match expr.as_ref() {
+            Expr::Bop(BinaryOp::Load, addr, _) => {
+                let (base, index) = address(addr)?;
+                res.push(Access {
+                    base,
+                    index,
+                    is_write: false,
+                });
+            }
+            Expr::Top(TernaryOp::Write, addr, _, _) => {
+                let (base, index) = address(addr)?;
+                res.push(Access {
+                    base,
+                    index,
+                    is_write: true,
+                });
+            }
+            Expr::Bop(BinaryOp::Print | BinaryOp::Free, _, _)
+            | Expr::Alloc(..)
+            | Expr::Call(..)
+            | Expr::If(..)
+            | Expr::Switch(..)
+            | Expr::DoWhile(..)
+            | Expr::Function(..)
+            | Expr::Symbolic(..) => return None,
+            _ => {}
+        }
+        todo.extend(expr.children_exprs());
+    }
+    Some(res)
+}
+
+/// A perfect loop nest. Indices are into the outer (`_o`) or inner (`_i`) loop's argument.
+struct Nest {
+    outer_inputs: Vec<RcExpr>,
+    outer_outputs: Vec<RcExpr>,
+    inner: RcExpr,
+    inner_inputs: Vec<RcExpr>,
+    inner_outputs: Vec<RcExpr>,
+    inner_body: RcExpr,
+    /// The outer counter and its bound
+    i_o: usize,
+    bound_o: usize,
+    /// The outer counter inside the inner loop
+    i_i: usize,
+    /// The inner counter, its bound and its start
+    j_i: usize,
+    bound_i: usize,
+    j_start: i64,
+}
+
+impl Nest {
+    fn new(outer: &RcExpr) -> Option<Nest> {
+        let Expr::DoWhile(outer_inputs, outer_body) = outer.as_ref() else {
+            return None;
+        };
+        let outer_inputs = try_split_inputs(outer_inputs.clone())?;
+        let outer_outputs = try_split_inputs(outer_body.clone())?;
+        let (i_o, bound_o, _) = unit_counter(&outer_outputs)?;
+
+        // everything else in the outer loop is passed through or comes from one inner loop
+        let mut inner = None;
+        let mut from_inner = vec![];
+        for k in
+            (0..outer_inputs.len()).filter(|k| *k != i_o && !is_passthrough(&outer_outputs, *k))
+        {
+            let Expr::Get(loop_, m) = outer_outputs[k + 1].as_ref() else {
+                return None;
+            };
+            match &inner {
+                None if matches!(loop_.as_ref(), Expr::DoWhile(..)) => inner = Some(loop_.clone()),
+                Some(inner) if Rc::ptr_eq(inner, loop_) => {}
+                _ => return None,
+            }
+            from_inner.push((*m, k));
+        }
+        let inner = inner?;
+        let Expr::DoWhile(inner_inputs, inner_body) = inner.as_ref().clone() else {
+            unreachable!()
+        };
+        let inner_inputs = try_split_inputs(inner_inputs)?;
+        let inner_outputs = try_split_inputs(inner_body.clone())?;
+        let (j_i, bound_i, Type::TupleT(inner_tys)) = unit_counter(&inner_outputs)? else {
+            return None;
+        };
+        let Expr::Const(Constant::Int(j_start), _, _) = inner_inputs[j_i].as_ref() else {
+            return None;
+        };
+
+        // the inner bound is invariant in the whole nest
+        let bound_i_o = arg_index(&inner_inputs[bound_i])?;
+        if !is_passthrough(&outer_outputs, bound_i_o) {
+            return None;
+        }
+
+        let mut i_i = None;
+        for m in (0..inner_inputs.len()).filter(|m| *m != j_i) {
+            let k = arg_index(&inner_inputs[m])?;
+            if k == i_o {
+                // the outer counter is passed in once, and not changed
+                if i_i.is_some() || !is_passthrough(&inner_outputs, m) {
+                    return None;
+                }
+                i_i = Some(m);
+            } else if !is_passthrough(&outer_outputs, k) || !is_passthrough(&inner_outputs, m) {
+                // otherwise, the inner loop only updates the state
+                let is_state = inner_tys.get(m) == Some(&BaseType::StateT);
+                if !is_state || !from_inner.contains(&(m, k)) {
+                    return None;
+                }
+            }
+        }
+        // every updated outer value is the same value of the inner loop,
+        // and not one of the counters (whose final values change)
+        if from_inner
+            .iter()
+            .any(|(m, k)| *m == j_i || Some(*m) == i_i || arg_index(&inner_inputs[*m]) != Some(*k))
+        {
+            return None;
+        }
+
+        Some(Nest {
+            outer_inputs,
+            outer_outputs,
+            inner,
+            inner_inputs,
+            inner_outputs,
+            inner_body,
+            i_o,
+            bound_o,
+            i_i: i_i?,
+            j_i,
+            bound_i,
+            j_start: *j_start,
+        })
+    }
+
+    /// The allocation an inner loop value points into, if it is known.
+    fn allocation(&self, m: usize) -> Option<*const Expr> {
+        if !is_passthrough(&self.inner_outputs, m) {
+            return None;
+        }
+        let k = arg_index(&self.inner_inputs[m])?;
+        if !is_passthrough(&self.outer_outputs, k) {
+            return None;
+        }
+        match self.outer_inputs[k].as_ref() {
+            Expr::Get(alloc, 0) if matches!(alloc.as_ref(), Expr::Alloc(..)) => {
+                Some(Rc::as_ptr(alloc))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the index is `i * n + j` or `j * n + i` (plus invariants),
+    /// where `n` is the bound of the counter with coefficient one.
+    fn is_injective(&self, index: &Poly) -> bool {
+        let (i, j) = (self.i_i, self.j_i);
+        let outer_bound = (0..self.inner_inputs.len()).find(|m| {
+            arg_index(&self.inner_inputs[*m]) == Some(self.bound_o)
+                && is_passthrough(&self.inner_outputs, *m)
+        });
+        let i_start = match self.outer_inputs[self.i_o].as_ref() {
+            Expr::Const(Constant::Int(start), _, _) => Some(*start),
+            _ => None,
+        };
+        let counters = |mono: &Vec<usize>| mono.contains(&i) || mono.contains(&j);
+        let with_counters = index
+            .iter()
+            .filter(|(mono, _)| counters(mono))
+            .map(|(mono, coeff)| (mono.clone(), *coeff))
+            .collect::<Poly>();
+        let row_major = |outer: usize, inner: usize, bound: usize| {
+            let mut mono = vec![outer, bound];
+            mono.sort();
+            with_counters == Poly::from([(vec![inner], 1), (mono, 1)])
+        };
+        (self.j_start >= 0 && row_major(i, j, self.bound_i))
+            || (i_start.is_some_and(|start| start >= 0)
+                && outer_bound.is_some_and(|bound| row_major(j, i, bound)))
+    }
+
+    /// Whether every pair of accesses that may touch the same memory,
+    /// one of them a write, does so in the same iteration.
+    fn dependences_are_local(&self, accesses: &[Access]) -> bool {
+        accesses.iter().filter(|w| w.is_write).all(|w| {
+            self.is_injective(&w.index)
+                && accesses.iter().all(|a| {
+                    if a.base == w.base {
+                        a.index == w.index
+                    } else {
+                        let (x, y) = (self.allocation(a.base), self.allocation(w.base));
+                        x.is_some() && y.is_some() && x != y
+                    }
+                })
+        })
+    }
+
+    /// Builds the nest with the loops swapped.
+    /// The result is a tuple like the outer loop's.
+    fn interchange(&self) -> RcExpr {
+        let (len_o, len_i) = (self.outer_inputs.len(), self.inner_inputs.len());
+
+        // the new inner loop counts i, with the outer bound added to its argument
+        let next_i = add(getat(self.i_i), int(1));
+        let inner_outputs = (0..len_i)
+            .map(|m| {
+                if m == self.j_i {
+                    getat(m)
+                } else if m == self.i_i {
+                    next_i.clone()
+                } else {
+                    self.inner_outputs[m + 1].clone()
+                }
+            })
+            .chain([getat(len_i)]);
+        let inner_body = parallel_vec(
+            [less_than(next_i.clone(), getat(len_i))]
+                .into_iter()
+                .chain(inner_outputs),
+        );
+        let inner_inputs = (0..len_i)
+            .map(|m| {
+                if m == self.j_i {
+                    getat(len_o)
+                } else {
+                    self.inner_inputs[m].clone()
+                }
+            })
+            .chain([getat(self.bound_o)]);
+        let inner = dowhile(parallel_vec(inner_inputs), inner_body);
+
+        // the new outer loop counts j, with j added to its argument
+        let next_j = add(getat(len_o), int(1));
+        let j_bound = arg_index(&self.inner_inputs[self.bound_i]).unwrap();
+        let outer_outputs = (0..len_o)
+            .map(|k| match self.outer_outputs[k + 1].as_ref() {
+                Expr::Get(_, m) if k != self.i_o && !is_passthrough(&self.outer_outputs, k) => {
+                    get(inner.clone(), *m)
+                }
+                _ => getat(k),
+            })
+            .chain([next_j.clone()]);
+        let outer_body = parallel_vec(
+            [less_than(next_j, getat(j_bound))]
+                .into_iter()
+                .chain(outer_outputs),
+        );
+        let outer_inputs = self.outer_inputs.iter().cloned().chain([int(self.j_start)]);
+        let outer = dowhile(parallel_vec(outer_inputs), outer_body);
+
+        // the outer counter ends one past its last value, like in the original loop
+        parallel_vec((0..len_o).map(|k| {
+            if k == self.i_o {
+                smax(
+                    add(get(outer.clone(), k), int(1)),
+                    get(outer.clone(), self.bound_o),
+                )
+            } else {
+                get(outer.clone(), k)
+            }
+        }))
+    }
+}
+
+/// The number of accesses that don't walk memory with unit stride
+/// as the counter changes.
+fn strided_accesses(accesses: &[Access], counter: usize) -> usize {
+    accesses
+        .iter()
+        .filter(|access| {
+            access
+                .index
+                .iter()
+                .any(|(mono, coeff)| mono.contains(&counter) && (mono.len() > 1 || coeff.abs() > 1))
+        })
+        .count()
+}
+
+/// The nest, when interchanging it is legal and changes how many accesses
+/// of the inner loop don't have unit stride.
+/// Also returns that number before and after the interchange.
+fn try_interchange(outer: &RcExpr) -> Option<(Nest, usize, usize)> {
+    let nest = Nest::new(outer)?;
+    let accesses = accesses(&nest.inner_body)?;
+    let before = strided_accesses(&accesses, nest.j_i);
+    let after = strided_accesses(&accesses, nest.i_i);
+    (before != after && nest.dependences_are_local(&accesses)).then_some((nest, before, after))
+}
+
+/// The loop that `value` comes from, when it is `(Get loop i)`.
+fn loop_of(value: &RcExpr) -> Option<RcExpr> {
+    match value.as_ref() {
+        Expr::Get(loop_, _) if matches!(loop_.as_ref(), Expr::DoWhile(..)) => Some(loop_.clone()),
+        _ => None,
+    }
+}
+
+/// The inner loop of a nest built by `Nest::interchange`.
+fn interchanged_inner_loop(interchanged: &RcExpr) -> Option<RcExpr> {
+    let outer = try_split_inputs(interchanged.clone())?
+        .iter()
+        .find_map(loop_of)?;
+    let Expr::DoWhile(_, outer_body) = outer.as_ref() else {
+        unreachable!()
+    };
+    try_split_inputs(outer_body.clone())?
+        .iter()
+        .find_map(loop_of)
+}
+
+/// Unions every loop nest in the functions `fns` that can be interchanged
+/// with the interchanged nest, and records the strided accesses of both inner loops.
+/// `program` already has contexts, and contexts for the interchanged nests
+/// are added using its `context_cache`, whose unions must be printed afterwards.
+/// Returns the new nests, which must outlive the caches since they are keyed by pointers.
+pub(crate) fn interchange_unions(
+    program: &TreeProgram,
+    fns: &[String],
+    context_cache: &mut ContextCache,
+    printed: &mut String,
+    tree_state: &mut TreeToEgglog,
+    term_cache: &mut IndexMap<Term, String>,
+) -> Vec<RcExpr> {
+    let types = program.typecheck();
+    let mut nests = vec![];
+    for name in fns {
+        let func = program.get_function(name).unwrap();
+
+        let mut seen = IndexSet::new();
+        // expressions to visit, with the argument type of their region
+        let mut todo = vec![(
+            func.func_body().unwrap().clone(),
+            func.func_input_ty().unwrap(),
+        )];
+        while let Some((expr, arg_ty)) = todo.pop() {
+            if !seen.insert(Rc::as_ptr(&expr)) {
+                continue;
+            }
+            if let Some((nest, before, after)) = try_interchange(&expr) {
+                let typed = program.override_expr_arg_types(&nest.interchange(), arg_ty);
+                let interchanged = typed.add_ctx_with_cache(expr.get_ctx().clone(), context_cache);
+                let new_inner = interchanged_inner_loop(&interchanged).unwrap();
+
+                let mut print = |value: &RcExpr| {
+                    let term = value.to_egglog_with(tree_state);
+                    print_with_intermediate_helper(&tree_state.termdag, term, term_cache, printed)
+                };
+                let original = print(&expr);
+                let alternative = print(&interchanged);
+                let facts = [(&nest.inner, before), (&new_inner, after)].map(|(loop_, strided)| {
+                    let Expr::DoWhile(inputs, body) = loop_.as_ref() else {
+                        unreachable!()
+                    };
+                    let (inputs, body) = (print(inputs), print(body));
+                    format!("(set (LoopStridedAccesses {inputs} {body}) {strided})")
+                });
+
+                writeln!(printed, "(union {original} {alternative})").unwrap();
+                for fact in facts {
+                    writeln!(printed, "{fact}").unwrap();
+                }
+                nests.extend([typed, interchanged]);
+                continue;
+            }
+
+            match expr.as_ref() {
+                Expr::DoWhile(inputs, body) => {
+                    todo.push((inputs.clone(), arg_ty));
+                    todo.push((body.clone(), types[&Rc::as_ptr(inputs)].clone()));
+                }
+                Expr::If(pred, inputs, thn, els) => {
+                    let branch_ty = types[&Rc::as_ptr(inputs)].clone();
+                    todo.push((pred.clone(), arg_ty.clone()));
+                    todo.push((inputs.clone(), arg_ty));
+                    todo.push((thn.clone(), branch_ty.clone()));
+                    todo.push((els.clone(), branch_ty));
+                }
+                Expr::Switch(pred, inputs, branches) => {
+                    let branch_ty = types[&Rc::as_ptr(inputs)].clone();
+                    todo.push((pred.clone(), arg_ty.clone()));
+                    todo.push((inputs.clone(), arg_ty));
+                    todo.extend(
+                        branches
+                            .iter()
+                            .map(|branch| (branch.clone(), branch_ty.clone())),
+                    );
+                }
+                _ => todo.extend(
+                    expr.children_exprs()
+                        .into_iter()
+                        .map(|child| (child, arg_ty.clone())),
+                ),
+            }
+        }
+    }
+    nests
+}
+
+#[cfg(test)]
+fn fill_array(index: impl Fn(RcExpr, RcExpr, RcExpr) -> RcExpr) -> TreeProgram {
+    use crate::ast::*;
+    // p = alloc(n * n)
+    // for i in 0..n: for j in 0..n: p[index(i, j, n)] = i * 10 + j
+    let p_and_state = alloc(0, mul(getat(0), getat(0)), getat(1), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let next_j = add(getat(1), int(1));
+    let inner = dowhile(
+        parallel!(getat(0), int(0), getat(1), getat(2), getat(3)),
+        parallel!(
+            less_than(next_j.clone(), getat(2)),
+            getat(0),
+            next_j,
+            getat(2),
+            getat(3),
+            write(
+                ptradd(getat(3), index(getat(0), getat(1), getat(2))),
+                add(mul(getat(0), int(10)), getat(1)),
+                getat(4)
+            )
+        ),
+    );
+    let next_i = add(getat(0), int(1));
+    let outer = dowhile(
+        parallel!(int(0), getat(0), p.clone(), get(p_and_state, 1)),
+        parallel!(
+            less_than(next_i.clone(), getat(1)),
+            next_i,
+            getat(1),
+            getat(2),
+            get(inner, 4)
+        ),
+    );
+    let val_and_state = load(ptradd(p.clone(), int(5)), get(outer, 3));
+    program!(function(
+        "main",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(
+            get(val_and_state.clone(), 0),
+            free(p, get(val_and_state, 1))
+        )
+    ),)
+    .with_arg_types()
+}
+
+/// Adds the program to the e-graph, with interchanged nests when `interchange` is set,
+/// and extracts it.
+#[cfg(test)]
+fn extract_nests(prog: &TreeProgram, interchange: bool) -> TreeProgram {
+    use crate::{
+        build_program,
+        greedy_dag_extractor::{greedy_dag_extract, serialized_egraph, DefaultCostModel},
+        InlinePolicy,
+    };
+    let fns = prog.fns();
+    let egglog_prog = build_program(
+        prog,
+        None,
+        &fns,
+        "",
+        None,
+        true,
+        interchange,
+        &InlinePolicy::default(),
+    );
+    let mut egraph = egglog::EGraph::default();
+    egraph.parse_and_run_program(None, &egglog_prog).unwrap();
+    let (serialized, unextractables) = serialized_egraph(egraph);
+    let (_cost, res) = greedy_dag_extract(
+        prog,
+        fns,
+        serialized,
+        unextractables,
+        &mut egglog::TermDag::default(),
+        DefaultCostModel,
+        true,
+        false,
+    );
+    res
+}
+
+#[cfg(test)]
+fn same_program(a: &TreeProgram, b: &TreeProgram) -> bool {
+    crate::are_progs_eq(a.add_dummy_ctx().0, b.add_dummy_ctx().0)
+}
+
+#[test]
+fn test_interchange_column_major_nest() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    let prog = fill_array(|i, j, n| add(mul(j, n), i));
+    assert!(same_program(&extract_nests(&prog, false), &prog));
+    // the interchanged nest writes with unit stride, so it is extracted
+    let interchanged = extract_nests(&prog, true);
+    assert!(!same_program(&interchanged, &prog));
+    for n in [3, 4] {
+        assert_eq!(
+            interpret_dag_prog(&prog, &tuplev!(intv(n), statev())),
+            interpret_dag_prog(&interchanged, &tuplev!(intv(n), statev()))
+        );
+    }
+}
+
+#[test]
+fn test_no_interchange_row_major_nest() {
+    use crate::ast::*;
+    // the inner loop already walks memory with unit stride,
+    // so the extractor keeps the original order
+    let prog = fill_array(|i, j, n| add(mul(i, n), j));
+    assert!(same_program(&extract_nests(&prog, true), &prog));
+    // every i writes the same elements, so the order matters
+    let prog = fill_array(|_i, j, n| mul(j, n));
+    assert!(same_program(&extract_nests(&prog, true), &prog));
+}
+
+#[test]
+fn test_poly_overflow_is_not_affine() {
+    use crate::ast::*;
+    let big = mul(getat(0), int(i64::MAX));
+    assert_eq!(as_poly(&add(big.clone(), big.clone())), None);
+    assert_eq!(as_poly(&sub(big.clone(), sub(int(0), big))), None);
+}
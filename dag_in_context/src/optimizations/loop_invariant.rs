@@ -39,6 +39,7 @@ fn is_invariant_rule_for_ctor(ctor: Constructor) -> Option<String> {
         // list handled in loop_invariant.egg
         // base cases are skipped
         // print, load, and Write are not invariant
+        // (loads are hoisted separately, see load_hoisting.egg)
         Constructor::Cons
         | Constructor::Nil
         | Constructor::Const
@@ -56,7 +57,9 @@ fn is_invariant_rule_for_ctor(ctor: Constructor) -> Option<String> {
                 })
                 .join(" ");
             let is_pure = match ctor {
-                Constructor::Call | Constructor::DoWhile => "(ExprIsPure expr)",
+                // calls can read memory, as long as the state is also invariant
+                Constructor::Call => "(CallIsReadOnly expr)",
+                Constructor::DoWhile => "(ExprIsPure expr)",
                 _ => "",
             };
 
@@ -265,3 +268,107 @@ fn test_invariant_hoist() -> crate::Result {
         vec![],
     )
 }
+
+#[test]
+fn test_invariant_load_hoist() -> crate::Result {
+    use crate::ast::*;
+    // p = alloc int 2;
+    // write p 10;
+    // do {
+    //   v = load p;        <- p isn't written in the loop
+    //   write (p + 1) (v + i);
+    //   i = i + 1;
+    // } while i < 3;
+    // return load (p + 1)
+    let p_and_state = alloc(0, int(2), getat(0), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let state = write(p.clone(), int(10), get(p_and_state, 1));
+    let val_and_state = load(getat(1), getat(2));
+    let next = add(getat(0), int(1));
+    let my_loop = dowhile(
+        parallel!(int(0), p.clone(), state),
+        parallel!(
+            less_than(next.clone(), int(3)),
+            next,
+            getat(1),
+            write(
+                ptradd(getat(1), int(1)),
+                add(get(val_and_state.clone(), 0), getat(0)),
+                get(val_and_state, 1)
+            ),
+        ),
+    );
+    let res = load(ptradd(p.clone(), int(1)), get(my_loop, 2));
+    let f = function(
+        "main",
+        tuplet!(statet()),
+        tuplet!(intt(), statet()),
+        parallel!(get(res.clone(), 0), free(p, get(res, 1))),
+    )
+    .func_with_arg_types();
+    let (f_with_ctx, cache) = f.func_add_ctx();
+
+    egglog_test(
+        &format!("{f_with_ctx}\n{}", cache.get_unions()),
+        "(check (DidMemOptimization \"hoist loop-invariant load\"))",
+        vec![program!(f,)],
+        tuplev!(statev()),
+        tuplev!(intv(12), statev()),
+        vec![],
+    )
+}
+
+#[test]
+fn test_invariant_load_hoist_removes_load_from_loop() -> crate::Result {
+    use crate::ast::*;
+    // p = alloc int 2;
+    // write p 10;
+    // do {
+    //   v = load p;
+    //   write (p + 1) (v + i);
+    //   i = i + 1;
+    // } while i < 3;
+    // return load (p + 1)
+    let p_and_state = alloc(0, int(2), getat(0), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let state = write(p.clone(), int(10), get(p_and_state, 1));
+    let val_and_state = load(getat(1), getat(2));
+    let next = add(getat(0), int(1));
+    let my_loop = dowhile(
+        parallel!(int(0), p.clone(), state),
+        parallel!(
+            less_than(next.clone(), int(3)),
+            next,
+            getat(1),
+            write(
+                ptradd(getat(1), int(1)),
+                add(get(val_and_state.clone(), 0), getat(0)),
+                get(val_and_state, 1)
+            ),
+        ),
+    );
+    let res = load(ptradd(p.clone(), int(1)), get(my_loop, 2));
+    let f = function(
+        "main",
+        tuplet!(statet()),
+        tuplet!(intt(), statet()),
+        parallel!(get(res.clone(), 0), free(p, get(res, 1))),
+    )
+    .func_with_arg_types();
+    let (f_with_ctx, cache) = f.func_add_ctx();
+
+    // In the new loop, the write uses the hoisted value
+    // and is on the loop's input state.
+    egglog_test(
+        &format!("{f_with_ctx}\n{}", cache.get_unions()),
+        "
+(check (= new_loop (DoWhile new_in new_body))
+       (= (Get new_body 4) hoisted)
+       (= hoisted (Get (Arg ty ctx) 3))
+       (= (Get new_body 3) (Top (Write) addr (Bop (Add) hoisted i) (Get (Arg ty ctx) 2))))",
+        vec![program!(f,)],
+        tuplev!(statev()),
+        tuplev!(intv(12), statev()),
+        vec![],
+    )
+}
@@ -57,3 +57,123 @@ fn loop_unroll_simple() -> crate::Result {
         vec![],
     )
 }
+
+#[test]
+fn loop_peel_first_iteration() -> crate::Result {
+    use crate::ast::*;
+    use crate::egglog_test;
+    // runs 3 times, so the first iteration is peeled off.
+    // It always continues, so the peeled loop starts at the second iteration.
+    let body = parallel!(
+        less_than(add(getat(0), int(1)), int(3)),
+        add(getat(0), int(1))
+    );
+    let prog = dowhile(parallel!(int(0)), body.clone()).add_arg_type(base(intt()));
+    let expected = dowhile(parallel!(int(1)), body)
+        .add_arg_type(base(intt()))
+        .add_symbolic_ctx();
+
+    egglog_test(
+        &format!("{prog}"),
+        &format!(
+            "
+(let loop {prog})
+(check (= loop (DoWhile inputs outputs))
+       (LoopPeeled inputs outputs))
+(check (= loop {expected}))"
+        ),
+        vec![
+            prog.to_program(base(intt()), tuplet!(intt())),
+            expected.to_program(base(intt()), tuplet!(intt())),
+        ],
+        intv(0),
+        tuplev!(intv(3)),
+        vec![],
+    )
+}
+
+#[test]
+fn loop_peel_unrolled_loop_separately() -> crate::Result {
+    use crate::ast::*;
+    use crate::egglog_test;
+    // runs 4 times, so it is both unrolled and peeled
+    let prog = dowhile(
+        parallel!(int(0)),
+        parallel!(
+            less_than(add(getat(0), int(1)), int(4)),
+            add(getat(0), int(1))
+        ),
+    )
+    .add_arg_type(base(intt()));
+
+    // the original and unrolled loops share an eclass,
+    // but their first iterations must stay apart
+    egglog_test(
+        &format!("{prog}"),
+        &format!(
+            "
+(let loop {prog})
+(check (= loop (DoWhile inputs body1))
+       (= loop (DoWhile inputs body2))
+       (!= body1 body2)
+       (LoopPeeled inputs body1)
+       (LoopPeeled inputs body2))
+(fail (check (= loop (DoWhile inputs body1))
+             (= loop (DoWhile inputs body2))
+             (!= body1 body2)
+             (= (LoopPeelFirst inputs body1) (LoopPeelFirst inputs body2))))"
+        ),
+        vec![prog.to_program(base(intt()), tuplet!(intt()))],
+        intv(0),
+        tuplev!(intv(4)),
+        vec![],
+    )
+}
+
+#[test]
+fn loop_iters_decrementing_symbolic_bound() -> crate::Result {
+    use crate::ast::*;
+    use crate::egglog_test;
+    use crate::schema::Expr;
+    // count down from 20 while the counter is greater than the second input,
+    // which is known to be between 5 and 10
+    let prog = dowhile(
+        parallel!(
+            int(20),
+            get(
+                tif(
+                    less_than(arg(), int(0)),
+                    arg(),
+                    single(int(5)),
+                    single(int(10))
+                ),
+                0
+            )
+        ),
+        parallel!(
+            greater_than(sub(getat(0), int(2)), getat(1)),
+            sub(getat(0), int(2)),
+            getat(1)
+        ),
+    )
+    .add_arg_type(base(intt()));
+    let Expr::DoWhile(inputs, outputs) = prog.add_symbolic_ctx().as_ref().clone() else {
+        unreachable!()
+    };
+
+    // the counter is checked at 18, 16, ..., so the loop runs 8 times
+    // when the bound is 5 and 5 times when it is 10
+    egglog_test(
+        &format!("{prog}"),
+        &format!(
+            "
+(check (= (LoopItersUpper {inputs} {outputs}) 8))
+(check (= (LoopItersLower {inputs} {outputs}) 5))
+(check (= (LoopNumItersGuess {inputs} {outputs}) 8))"
+        ),
+        vec![prog.to_program(base(intt()), tuplet!(intt(), intt()))],
+        intv(3),
+        tuplev!(intv(10), intv(10)),
+        vec![],
+    )
+}
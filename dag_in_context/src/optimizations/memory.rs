@@ -1,3 +1,5 @@
+use crate::config::{MAX_POINTEE_ALLOCS, POINTS_TO_ITERS_BEFORE_WIDENING};
+
 // Signature of an egglog function, for metaprogramming
 fn listlike(el_tys: Vec<&str>, el_relations: Vec<&str>) -> String {
     assert!(!el_tys.is_empty());
@@ -305,8 +307,18 @@ pub(crate) fn rules() -> String {
 (rewrite (Union-PtrPointees _ (PointsAnywhere))
          (PointsAnywhere)
          :ruleset always-run)
+; the union is bounded, so that pointers into many allocations are summarized
 (rewrite (Union-PtrPointees (PointsTo x) (PointsTo y))
          (PointsTo (Union-List<i64+IntInterval> x y))
+         :when ((= xlen (Length-List<i64+IntInterval> x))
+                (= ylen (Length-List<i64+IntInterval> y))
+                (<= (+ xlen ylen) {MAX_POINTEE_ALLOCS}))
+         :ruleset always-run)
+(rewrite (Union-PtrPointees (PointsTo x) (PointsTo y))
+         (PointsAnywhere)
+         :when ((= xlen (Length-List<i64+IntInterval> x))
+                (= ylen (Length-List<i64+IntInterval> y))
+                (> (+ xlen ylen) {MAX_POINTEE_ALLOCS}))
          :ruleset always-run)
 (constructor Intersect-PtrPointees (PtrPointees PtrPointees) PtrPointees)
 (rewrite (Intersect-PtrPointees (PointsAnywhere) x)
@@ -357,6 +369,48 @@ pub(crate) fn rules() -> String {
     )
 }
 
+/// The steps of the points-to fixed point for loops.
+/// Offsets are forgotten after `POINTS_TO_ITERS_BEFORE_WIDENING` iterations.
+/// These use `PointsToCellsAtIter` from memory.egg, so they come after it.
+pub(crate) fn loop_iteration_rules() -> String {
+    let widen_after = POINTS_TO_ITERS_BEFORE_WIDENING;
+    format!(
+        "
+; Note that this rule is bounded by ruleset memory-analysis
+(rule ((= pointees0 (PointsToCellsAtIter aps inputs pred-body i))
+       (= pointees1 (PointsToCellsAtIter aps inputs pred-body (succ i)))
+       (Resolved-Pointees pointees0)
+       (Resolved-Pointees pointees1)
+       (!= pointees0 pointees1)
+       (< i {widen_after}))
+      ((set (PointsToCellsAtIter aps inputs pred-body (+ i 2))
+            (UnionPointees
+              pointees1
+              (PointeesDropFirst
+                (PointsToCells pred-body pointees1)))))
+      :ruleset memory-analysis)
+
+; After a few iterations, offsets that keep changing (like a pointer
+; incremented by the loop) would never reach a fixed point.
+; Widen by summarizing each alloc id with any offset.
+; Sets of alloc ids are bounded, so this terminates.
+(rule ((= pointees0 (PointsToCellsAtIter aps inputs pred-body i))
+       (= pointees1 (PointsToCellsAtIter aps inputs pred-body (succ i)))
+       (Resolved-Pointees pointees0)
+       (Resolved-Pointees pointees1)
+       (!= pointees0 pointees1)
+       (>= i {widen_after}))
+      ((set (PointsToCellsAtIter aps inputs pred-body (+ i 2))
+            (ForgetOffsets
+              (UnionPointees
+                pointees1
+                (PointeesDropFirst
+                  (PointsToCells pred-body pointees1))))))
+      :ruleset memory-analysis)
+"
+    )
+}
+
 #[cfg(test)]
 use crate::egglog_test;
 
@@ -370,8 +424,8 @@ use crate::Value;
 use main_error::MainError;
 
 #[cfg(test)]
-// TODO we don't use memory in the main schedule yet
-// so here enable it for tests
+// The main schedule runs a bounded number of rounds
+// of the points-to analysis, so here run more for tests
 fn memory_egglog_test(
     build: &str,
     check: &str,
@@ -384,12 +438,13 @@ fn memory_egglog_test(
         build,
         &format!(
             "
-    ;; TODO we don't run memory in the main loop right now
+    ;; run the points-to analysis for more rounds than the main schedule
     (run-schedule
         (repeat 6
         (saturate
             always-run
             memory-helpers)
+        memory-analysis
         memory))        
         {check}"
         ),
@@ -435,6 +490,41 @@ fn load_after_write() -> crate::Result {
     )
 }
 
+#[test]
+fn forwarded_load_keeps_state_edge() -> crate::Result {
+    use crate::ast::*;
+    // ptr = alloc int 1;
+    // write ptr 2;
+    // res = load ptr;
+    // print res
+    // =>
+    // the print uses the written value, but still the state of the load
+    let one = int_ty(1, Type::Base(BaseType::IntT));
+    let two = int(2).with_arg_types(tuplet!(statet()), Type::Base(intt()));
+    let orig_state = get(arg_ty(tuplet!(statet())), 0);
+    let ptr_and_state = alloc(0, one, orig_state.clone(), pointert(intt()));
+    let ptr = get(ptr_and_state.clone(), 0);
+    let state = get(ptr_and_state, 1);
+    let written = write(ptr.clone(), two.clone(), state);
+    let val_and_state = load(ptr, written.clone());
+    let val = get(val_and_state.clone(), 0);
+    let state = get(val_and_state, 1);
+    let res = tprint(val, state.clone());
+
+    memory_egglog_test(
+        &format!("{res}"),
+        &format!(
+            "
+        (check (= {res} (Bop (Print) {two} {state})))
+        (fail (check (= {state} {written})))"
+        ),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
+
 #[test]
 fn load_after_write_without_alias() -> crate::Result {
     use crate::ast::*;
@@ -471,12 +561,13 @@ fn load_after_write_without_alias() -> crate::Result {
         &format!("{f}"),
         &format!(
             "
-        ;; TODO we don't run memory in the main loop right now
+        ;; run the points-to analysis for more rounds than the main schedule
         (run-schedule
           (repeat 6
             (saturate
                 always-run
                 memory-helpers)
+            memory-analysis
             memory))
         
         (check (= {res} (Bop (Print) {two} rest)))"
@@ -542,12 +633,13 @@ fn simple_loop_swap() -> crate::Result {
         &format!("{f}"),
         &format!(
             "
-        ;; TODO we don't run memory in the main loop right now
+        ;; run the points-to analysis for more rounds than the main schedule
         (run-schedule
           (repeat 6
             (saturate
                 always-run
                 memory-helpers)
+            memory-analysis
             memory))
         (let ten {ten}) (let val {val}) (check (= val ten))"
         ),
@@ -676,12 +768,13 @@ fn redundant_load_elim() -> crate::Result {
         &format!("{f}"),
         &format!(
             "
-        ;; TODO we don't run memory in the main loop right now
+        ;; run the points-to analysis for more rounds than the main schedule
         (run-schedule
           (repeat 6
             (saturate
                 always-run
                 memory-helpers)
+            memory-analysis
             memory))
         (print-function PointsToExpr 1000)
         (check (= {res} (Bop (Print) {load1_val} rest)))"
@@ -692,3 +785,199 @@ fn redundant_load_elim() -> crate::Result {
         vec![],
     )
 }
+
+#[test]
+fn load_after_write_without_alias_default_schedule() -> crate::Result {
+    use crate::ast::*;
+    // Like load_after_write_without_alias, but the two pointers are
+    // only known not to alias through the points-to analysis,
+    // which the default schedule now runs.
+    let one = int(1);
+    let two = int(2).with_arg_types(tuplet!(statet()), Type::Base(intt()));
+    let ptr_and_state = alloc(0, one.clone(), getat(0), pointert(intt()));
+    let ptr1 = get(ptr_and_state.clone(), 0);
+    let state = get(ptr_and_state, 1);
+    let ptr_and_state = alloc(1, one, state, pointert(intt()));
+    let ptr2 = get(ptr_and_state.clone(), 0);
+    let state = get(ptr_and_state, 1);
+    let state = write(ptr1.clone(), two.clone(), state);
+    let state = write(ptr2, int(3), state);
+    let val_and_state = load(ptr1, state);
+    let res = tprint(get(val_and_state.clone(), 0), get(val_and_state, 1))
+        .with_arg_types(tuplet!(statet()), Type::Base(statet()));
+    let f = function("main", tuplet!(statet()), Type::Base(statet()), res.clone())
+        .func_with_arg_types();
+    egglog_test(
+        &format!("{f}"),
+        &format!("(check (= {res} (Bop (Print) {two} rest)))"),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
+
+#[test]
+fn incremented_pointer_in_loop() -> crate::Result {
+    // p = alloc(0, 4, int*)
+    // q = alloc(1, 1, int*)
+    // do {
+    //   p = ptradd(p, 1)
+    // } while true;
+    // *q = 5
+    // *p = 7
+    // // p's offsets never reach a fixed point, but it still
+    // // only points into alloc 0, so loading q gives 5
+    use crate::ast::*;
+    let p_and_state = alloc(0, int(4), getat(0), pointert(intt()));
+    let q_and_state = alloc(1, int(1), get(p_and_state.clone(), 1), pointert(intt()));
+    let q = get(q_and_state.clone(), 0);
+    let loop1 = dowhile(
+        parallel!(get(q_and_state, 1), get(p_and_state, 0)),
+        parallel!(ttrue(), getat(0), ptradd(getat(1), int(1))),
+    )
+    .with_arg_types(tuplet!(statet()), tuplet!(statet(), pointert(intt())));
+    let state = write(q.clone(), int(5), get(loop1.clone(), 0));
+    let state = write(get(loop1, 1), int(7), state);
+    let val_and_state = load(q, state);
+    let val = get(val_and_state, 0).with_arg_types(tuplet!(statet()), Type::Base(intt()));
+    let five = int(5).with_arg_types(tuplet!(statet()), Type::Base(intt()));
+    let f =
+        function("main", tuplet!(statet()), Type::Base(intt()), val.clone()).func_with_arg_types();
+    memory_egglog_test(
+        &format!("{f}"),
+        &format!("(let five {five}) (let val {val}) (check (= val five))"),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
+
+#[test]
+fn dead_store_with_unrelated_write_between() -> crate::Result {
+    use crate::ast::*;
+    // p = alloc int 2;
+    // q = ptradd p 1;
+    // write p 1;   <- dead
+    // write q 2;
+    // write p 3;
+    // print (load p)
+    let ty = |e: crate::schema::RcExpr| e.with_arg_types(tuplet!(statet()), Type::Base(statet()));
+    let p_and_state = alloc(0, int(2), getat(0), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let q = ptradd(p.clone(), int(1));
+    let state = get(p_and_state, 1);
+    let last_write = write(
+        p.clone(),
+        int(3),
+        write(q.clone(), int(2), write(p.clone(), int(1), state.clone())),
+    );
+    let val_and_state = load(p.clone(), last_write.clone());
+    let res = ty(tprint(get(val_and_state.clone(), 0), get(val_and_state, 1)));
+    let expected = ty(write(p, int(3), write(q, int(2), state)));
+    let last_write = ty(last_write);
+    egglog_test(
+        &format!("{res}"),
+        &format!("(check (= {last_write} {expected}))"),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
+
+#[test]
+fn dead_store_with_load_between() -> crate::Result {
+    use crate::ast::*;
+    // p = alloc int 2;
+    // q = ptradd p 1;
+    // write p 1;   <- dead
+    // x = load q;
+    // write p 3;
+    // print x
+    let ty = |e: crate::schema::RcExpr| e.with_arg_types(tuplet!(statet()), Type::Base(statet()));
+    let p_and_state = alloc(0, int(2), getat(0), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let q = ptradd(p.clone(), int(1));
+    let state = get(p_and_state, 1);
+    let x = load(q.clone(), write(p.clone(), int(1), state.clone()));
+    let last_write = write(p.clone(), int(3), get(x.clone(), 1));
+    let res = ty(tprint(get(x, 0), last_write.clone()));
+    let new_x = load(q, state);
+    let expected = ty(write(p, int(3), get(new_x.clone(), 1)));
+    let new_x_val = get(new_x, 0).with_arg_types(tuplet!(statet()), Type::Base(intt()));
+    let last_write = ty(last_write);
+    // the print's value and state both come from the rebuilt load
+    egglog_test(
+        &format!("{res}"),
+        &format!(
+            "(check (= {last_write} {expected}))
+             (check (= {res} (Bop (Print) {new_x_val} {expected})))"
+        ),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
+
+#[test]
+fn unobserved_allocation_removed() -> crate::Result {
+    use crate::ast::*;
+    // p = alloc int 1;
+    // write p 5;
+    // free p;
+    // print 7
+    // =>
+    // print 7
+    let ty = |e: crate::schema::RcExpr| e.with_arg_types(tuplet!(statet()), Type::Base(statet()));
+    let p_and_state = alloc(0, int(1), getat(0), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let state = write(p.clone(), int(5), get(p_and_state, 1));
+    let res = ty(tprint(int(7), free(p, state)));
+    let expected = ty(tprint(int(7), getat(0)));
+    egglog_test(
+        &format!("{res}"),
+        &format!("(check (= {res} {expected}))"),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
+
+#[test]
+fn unobserved_allocation_with_other_accesses_between() -> crate::Result {
+    use crate::ast::*;
+    // q = alloc int 1;
+    // p = alloc int 1;
+    // write p 5;
+    // write q 6;
+    // x = load q;
+    // free p;
+    // print x
+    // =>
+    // q = alloc int 1;
+    // write q 6;
+    // x = load q;
+    // print x
+    let ty = |e: crate::schema::RcExpr| e.with_arg_types(tuplet!(statet()), Type::Base(statet()));
+    let q_and_state = alloc(0, int(1), getat(0), pointert(intt()));
+    let q = get(q_and_state.clone(), 0);
+    let p_and_state = alloc(1, int(1), get(q_and_state.clone(), 1), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let state = write(p.clone(), int(5), get(p_and_state, 1));
+    let x = load(q.clone(), write(q.clone(), int(6), state));
+    let res = ty(tprint(get(x.clone(), 0), free(p, get(x, 1))));
+    let new_x = load(q.clone(), write(q, int(6), get(q_and_state, 1)));
+    let expected = ty(tprint(get(new_x.clone(), 0), get(new_x, 1)));
+    egglog_test(
+        &format!("{res}"),
+        &format!("(check (= {res} {expected}))"),
+        vec![],
+        emptyv(),
+        emptyv(),
+        vec![],
+    )
+}
@@ -1,12 +1,26 @@
 pub mod body_contains;
+mod bounds_checks;
+mod branch_threading;
+pub(crate) mod call_graph;
+mod code_placement;
 pub mod conditional_invariant_code_motion;
+pub mod dead_functions;
+pub(crate) mod div_by_const;
+pub(crate) mod effect_summary;
+mod fast_math;
 pub mod function_inlining;
 pub mod is_resolved;
 pub mod is_valid;
 pub mod ivt;
+pub mod loop_fusion;
+pub mod loop_interchange;
 pub mod loop_invariant;
 pub mod loop_unroll;
 pub mod memory;
+mod partial_redundancy;
 pub mod passthrough;
 mod peepholes;
+pub mod scalar_replacement;
+pub mod specialize;
 pub mod switch_rewrites;
+pub mod tail_recursion;
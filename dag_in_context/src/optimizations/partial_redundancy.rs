@@ -0,0 +1,148 @@
+#[cfg(test)]
+use crate::egglog_test;
+
+#[test]
+fn pre_hoists_branch_expr() -> crate::Result {
+    use crate::ast::*;
+
+    // (if x < 0 {x * y} else {0}, x * y)
+    // x * y is hoisted above the if and passed in as a third input,
+    // or computed in the else branch too and returned as a second output
+    let ty = tuplet!(intt(), intt());
+    let body = parallel!(
+        get(
+            tif(
+                less_than(getat(0), int(0)),
+                parallel!(getat(0), getat(1)),
+                single(mul(getat(0), getat(1))),
+                single(int(0)),
+            ),
+            0
+        ),
+        mul(getat(0), getat(1))
+    );
+    let prog = function("main", ty.clone(), ty.clone(), body)
+        .func_with_arg_types()
+        .to_program(ty.clone(), ty.clone());
+    let (with_context, cache) = prog.add_context();
+
+    let (after_join, after_join_cache) = mul(getat(0), getat(1))
+        .with_arg_types(ty.clone(), base(intt()))
+        .add_ctx(infunc("main"));
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!(
+            "(let after_join {after_join})\n{}
+(check (If pred (Concat ins (Single after_join)) thn els))
+(check (= after_join (Get (If pred2 ins2 (Concat thn2 (Single x)) (Concat els2 (Single y))) 1)))",
+            after_join_cache.get_unions()
+        ),
+        vec![with_context],
+        tuplev!(intv(-2), intv(3)),
+        tuplev!(intv(-6), intv(-6)),
+        vec![],
+    )
+}
+
+#[test]
+fn pre_needs_copy_after_join() -> crate::Result {
+    use crate::ast::*;
+
+    // (if x < 0 {x * y} else {0}, x + y)
+    // nothing after the join matches x * y, so it stays in the branch
+    let ty = tuplet!(intt(), intt());
+    let body = parallel!(
+        get(
+            tif(
+                less_than(getat(0), int(0)),
+                parallel!(getat(0), getat(1)),
+                single(mul(getat(0), getat(1))),
+                single(int(0)),
+            ),
+            0
+        ),
+        add(getat(0), getat(1))
+    );
+    let prog = function("main", ty.clone(), ty.clone(), body)
+        .func_with_arg_types()
+        .to_program(ty.clone(), ty.clone());
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "(fail (check (PREHoisted if_e e)))",
+        vec![with_context],
+        tuplev!(intv(-2), intv(3)),
+        tuplev!(intv(-6), intv(1)),
+        vec![],
+    )
+}
+
+#[test]
+fn pre_needs_same_expr_after_join() -> crate::Result {
+    use crate::ast::*;
+
+    // (if x < 0 {x * y} else {0}, x * x)
+    // the multiply after the join has other operands, so x * y stays in the branch
+    let ty = tuplet!(intt(), intt());
+    let body = parallel!(
+        get(
+            tif(
+                less_than(getat(0), int(0)),
+                parallel!(getat(0), getat(1)),
+                single(mul(getat(0), getat(1))),
+                single(int(0)),
+            ),
+            0
+        ),
+        mul(getat(0), getat(0))
+    );
+    let prog = function("main", ty.clone(), ty.clone(), body)
+        .func_with_arg_types()
+        .to_program(ty.clone(), ty.clone());
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "(fail (check (PRERedundant if_e e)))",
+        vec![with_context],
+        tuplev!(intv(-2), intv(3)),
+        tuplev!(intv(-6), intv(4)),
+        vec![],
+    )
+}
+
+#[test]
+fn pre_keeps_division_in_branch() -> crate::Result {
+    use crate::ast::*;
+
+    // (if y != 0 {x / y} else {0}, x / y)
+    // y may be zero, so the division isn't hoisted above the if
+    let ty = tuplet!(intt(), intt());
+    let body = parallel!(
+        get(
+            tif(
+                not(eq(getat(1), int(0))),
+                parallel!(getat(0), getat(1)),
+                single(div(getat(0), getat(1))),
+                single(int(0)),
+            ),
+            0
+        ),
+        div(getat(0), getat(1))
+    );
+    let prog = function("main", ty.clone(), ty.clone(), body)
+        .func_with_arg_types()
+        .to_program(ty.clone(), ty.clone());
+    let (with_context, cache) = prog.add_context();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        "(fail (check (PREHoisted if_e e)))",
+        vec![with_context],
+        tuplev!(intv(-6), intv(3)),
+        tuplev!(intv(-2), intv(-2)),
+        vec![],
+    )
+}
@@ -0,0 +1,857 @@
+//! Replaces small allocations that don't escape with plain values.
+//!
+//! An allocation qualifies when it has a constant size of at most
+//! `MAX_SCALAR_REPLACEMENT_CELLS`, is freed in the same region,
+//! and its pointer is only used as the address of loads and writes
+//! (directly or with a constant offset), as an input of regions, and by that free.
+//! Every access is then on the state edge between the allocation and the free,
+//! so we walk that edge, keeping the value of each cell,
+//! and rebuild it without the allocation, its accesses and the free.
+//!
+//! The edge may pass through regions and calls. Those that don't get the
+//! pointer can't access the allocation and are kept as is.
+//! A pointer passed into an if, switch or loop is followed to the region's
+//! argument, and the region is rebuilt to take the cells as extra inputs and
+//! outputs instead. The region may pass the pointer back out unchanged
+//! (a loop only at the same index), but any other use of it escapes.
+//! Escape is checked on the syntax of this pass's program, since the
+//! points-to facts in `memory.egg` only exist inside the e-graph.
+
+use std::rc::Rc;
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    ast::{
+        alloc, dowhile, float, free, get, getat, int, load, parallel_vec, switch_vec, tfalse, tif,
+        tprint, write,
+    },
+    config::MAX_SCALAR_REPLACEMENT_CELLS,
+    remove_dead_code_nodes::try_split_inputs,
+    schema::{BaseType, BinaryOp, Constant, Expr, RcExpr, TernaryOp, TreeProgram},
+};
+
+/// The parents of every node in `expr`.
+fn parents(expr: &RcExpr) -> IndexMap<*const Expr, Vec<RcExpr>> {
+    let mut res: IndexMap<*const Expr, Vec<RcExpr>> = IndexMap::new();
+    let mut seen = IndexSet::new();
+    let mut todo = vec![expr.clone()];
+    while let Some(expr) = todo.pop() {
+        if !seen.insert(Rc::as_ptr(&expr)) {
+            continue;
+        }
+        for child in expr.children_exprs() {
+            res.entry(Rc::as_ptr(&child))
+                .or_default()
+                .push(expr.clone());
+            todo.push(child);
+        }
+    }
+    res
+}
+
+/// The value of a cell before it is written.
+/// Loading it is undefined behavior, so any value of the right type works.
+fn placeholder(ty: &BaseType) -> Option<RcExpr> {
+    match ty {
+        BaseType::IntT => Some(int(0)),
+        BaseType::BoolT => Some(tfalse()),
+        BaseType::FloatT => Some(float(0.0)),
+        BaseType::PointerT(_) | BaseType::StateT => None,
+    }
+}
+
+/// Replaces nodes (by pointer), sharing a cache between calls.
+fn rewrite(
+    expr: &RcExpr,
+    replacements: &IndexMap<*const Expr, RcExpr>,
+    cache: &mut IndexMap<*const Expr, RcExpr>,
+) -> RcExpr {
+    if let Some(res) = replacements.get(&Rc::as_ptr(expr)) {
+        return res.clone();
+    }
+    if let Some(res) = cache.get(&Rc::as_ptr(expr)) {
+        return res.clone();
+    }
+    let res = expr.map_expr_children(|child| rewrite(child, replacements, cache));
+    cache.insert(Rc::as_ptr(expr), res.clone());
+    res
+}
+
+/// Whether `expr` is `(Get (Arg ..) index)`.
+fn is_arg_get(expr: &RcExpr, index: usize) -> bool {
+    matches!(expr.as_ref(), Expr::Get(arg, i) if *i == index && matches!(arg.as_ref(), Expr::Arg(..)))
+}
+
+/// The `(Get (Arg ..) index)` nodes in the region whose body is `body`,
+/// not counting nested regions.
+fn arg_gets(body: &RcExpr, index: usize) -> Vec<RcExpr> {
+    let mut res = vec![];
+    let mut seen = IndexSet::new();
+    let mut todo = vec![body.clone()];
+    while let Some(expr) = todo.pop() {
+        if !seen.insert(Rc::as_ptr(&expr)) {
+            continue;
+        }
+        if is_arg_get(&expr, index) {
+            res.push(expr.clone());
+        }
+        match expr.as_ref() {
+            Expr::DoWhile(inputs, _) => todo.push(inputs.clone()),
+            Expr::If(pred, inputs, _, _) | Expr::Switch(pred, inputs, _) => {
+                todo.extend([pred.clone(), inputs.clone()])
+            }
+            _ => todo.extend(expr.children_exprs()),
+        }
+    }
+    res
+}
+
+/// The inputs of a region and its bodies (branches, or the loop's predicate and outputs).
+fn region_parts(region: &RcExpr) -> Option<(RcExpr, Vec<RcExpr>)> {
+    match region.as_ref() {
+        Expr::DoWhile(inputs, body) => Some((inputs.clone(), vec![body.clone()])),
+        Expr::If(_, inputs, thn, els) => Some((inputs.clone(), vec![thn.clone(), els.clone()])),
+        Expr::Switch(_, inputs, branches) => Some((inputs.clone(), branches.clone())),
+        _ => None,
+    }
+}
+
+/// The outputs of a region that pass through its input `k` unchanged,
+/// if every body agrees on them.
+/// A loop has to pass it through at `k`, or the pointer would change between iterations.
+fn passed_through(region: &RcExpr, k: usize) -> Option<Vec<usize>> {
+    let (_, bodies) = region_parts(region)?;
+    let mut res = None;
+    for body in bodies {
+        let outputs = try_split_inputs(body)?;
+        let outputs = match region.as_ref() {
+            Expr::DoWhile(..) => &outputs[1..],
+            _ => &outputs[..],
+        };
+        let here = (0..outputs.len())
+            .filter(|j| is_arg_get(&outputs[*j], k))
+            .collect::<Vec<_>>();
+        if *res.get_or_insert_with(|| here.clone()) != here {
+            return None;
+        }
+    }
+    let res = res?;
+    (!matches!(region.as_ref(), Expr::DoWhile(..)) || res == [k]).then_some(res)
+}
+
+/// The tuples `expr` is an element of, with the node using each tuple.
+fn tuple_users(
+    expr: &RcExpr,
+    parents: &IndexMap<*const Expr, Vec<RcExpr>>,
+) -> Vec<(RcExpr, RcExpr)> {
+    let mut res = vec![];
+    let mut todo = parents
+        .get(&Rc::as_ptr(expr))
+        .into_iter()
+        .flatten()
+        .filter(|parent| matches!(parent.as_ref(), Expr::Single(_)))
+        .cloned()
+        .collect::<Vec<_>>();
+    while let Some(tuple) = todo.pop() {
+        for user in parents.get(&Rc::as_ptr(&tuple)).into_iter().flatten() {
+            if matches!(user.as_ref(), Expr::Concat(..)) {
+                todo.push(user.clone());
+            } else {
+                res.push((user.clone(), tuple.clone()));
+            }
+        }
+    }
+    res
+}
+
+/// A small allocation that may be replaced by values.
+struct Candidate {
+    size: usize,
+}
+
+/// The part of a region that sees the allocation.
+struct Scope {
+    /// Nodes that are the pointer itself, without an offset.
+    /// Starts with `(Get alloc 0)` nodes, or the region's argument,
+    /// and grows with the outputs of regions that pass the pointer through.
+    pointers: Vec<RcExpr>,
+    /// Where the state edge starts in this region
+    states: Vec<RcExpr>,
+    /// What the starting states are replaced with, if anything
+    state_in: Option<RcExpr>,
+    /// The region this scope is a body of, which may pass the pointer out
+    region: Option<RcExpr>,
+    /// The value of each cell where the state edge starts
+    values: Vec<RcExpr>,
+}
+
+/// The uses of the pointer in a scope.
+struct Accesses {
+    /// Loads and writes through the pointer
+    ops: IndexSet<*const Expr>,
+    frees: Vec<RcExpr>,
+    /// Regions the pointer is passed into, with its index in their inputs
+    regions: IndexMap<*const Expr, usize>,
+}
+
+impl Candidate {
+    fn new(
+        alloc: &RcExpr,
+        parents: &IndexMap<*const Expr, Vec<RcExpr>>,
+    ) -> Option<(Candidate, Scope)> {
+        let Expr::Alloc(_, amount, state_in, BaseType::PointerT(elem)) = alloc.as_ref() else {
+            return None;
+        };
+        let Expr::Const(Constant::Int(size), _, _) = amount.as_ref() else {
+            return None;
+        };
+        let size = usize::try_from(*size).ok()?;
+        if size == 0 || size > MAX_SCALAR_REPLACEMENT_CELLS {
+            return None;
+        }
+        let (mut pointers, mut states) = (vec![], vec![]);
+        for parent in parents.get(&Rc::as_ptr(alloc))? {
+            match parent.as_ref() {
+                Expr::Get(_, 0) => pointers.push(parent.clone()),
+                Expr::Get(_, 1) => states.push(parent.clone()),
+                _ => return None,
+            }
+        }
+        let scope = Scope {
+            pointers,
+            states,
+            state_in: Some(state_in.clone()),
+            region: None,
+            values: vec![placeholder(elem)?; size],
+        };
+        Some((Candidate { size }, scope))
+    }
+
+    /// Builds the replacements that remove the allocation.
+    fn replacements(
+        &self,
+        mut scope: Scope,
+        parents: &IndexMap<*const Expr, Vec<RcExpr>>,
+    ) -> Option<IndexMap<*const Expr, RcExpr>> {
+        let accesses = self.accesses(&mut scope, parents)?;
+        let [free] = accesses.frees.as_slice() else {
+            return None;
+        };
+        let Expr::Bop(BinaryOp::Free, _, state) = free.as_ref() else {
+            unreachable!()
+        };
+        let (chain, _) = scope.longest_chain(&[state.clone()], &mut IndexMap::new())?;
+        let (mut replacements, _) = self.rebuild(&scope, &accesses, chain, parents)?;
+        let cur = rewrite(state, &replacements, &mut IndexMap::new());
+        replacements.insert(Rc::as_ptr(free), cur);
+        Some(replacements)
+    }
+
+    /// Checks that the pointer doesn't escape the scope, returning its uses.
+    /// Adds the outputs of regions that pass the pointer through to the scope's pointers.
+    fn accesses(
+        &self,
+        scope: &mut Scope,
+        parents: &IndexMap<*const Expr, Vec<RcExpr>>,
+    ) -> Option<Accesses> {
+        let mut res = Accesses {
+            ops: IndexSet::new(),
+            frees: vec![],
+            regions: IndexMap::new(),
+        };
+        let mut addrs = scope.pointers.clone();
+        while let Some(addr) = addrs.pop() {
+            for parent in parents.get(&Rc::as_ptr(&addr)).into_iter().flatten() {
+                match parent.as_ref() {
+                    Expr::Bop(BinaryOp::PtrAdd, ptr, _)
+                        if Rc::ptr_eq(ptr, &addr) && self.cell(scope, parent).is_some() =>
+                    {
+                        addrs.push(parent.clone())
+                    }
+                    Expr::Bop(BinaryOp::Load, ptr, state)
+                        if Rc::ptr_eq(ptr, &addr) && !Rc::ptr_eq(state, &addr) =>
+                    {
+                        res.ops.insert(Rc::as_ptr(parent));
+                    }
+                    Expr::Top(TernaryOp::Write, ptr, val, state)
+                        if Rc::ptr_eq(ptr, &addr)
+                            && !Rc::ptr_eq(val, &addr)
+                            && !Rc::ptr_eq(state, &addr) =>
+                    {
+                        res.ops.insert(Rc::as_ptr(parent));
+                    }
+                    Expr::Bop(BinaryOp::Free, ptr, state)
+                        if scope.is_pointer(ptr) && !Rc::ptr_eq(state, &addr) =>
+                    {
+                        res.frees.push(parent.clone())
+                    }
+                    // the pointer is passed into a region, or out of this one
+                    Expr::Single(_) if scope.is_pointer(&addr) => {}
+                    _ => return None,
+                }
+            }
+            if !scope.is_pointer(&addr) {
+                continue;
+            }
+            for (user, tuple) in tuple_users(&addr, parents) {
+                let (inputs, _) = region_parts(&user)?;
+                if !Rc::ptr_eq(&inputs, &tuple) {
+                    // only the region this scope is a body of may pass it out
+                    if scope.region.as_ref().is_some_and(|r| Rc::ptr_eq(r, &user)) {
+                        continue;
+                    }
+                    return None;
+                }
+                let slots = try_split_inputs(inputs)?
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, input)| Rc::ptr_eq(input, &addr))
+                    .map(|(k, _)| k)
+                    .collect::<Vec<_>>();
+                let [k] = slots.as_slice() else {
+                    return None;
+                };
+                match res.regions.insert(Rc::as_ptr(&user), *k) {
+                    Some(other) if other != *k => return None,
+                    Some(_) => {}
+                    None => {
+                        let outputs = passed_through(&user, *k)?;
+                        for output in parents.get(&Rc::as_ptr(&user)).into_iter().flatten() {
+                            if matches!(output.as_ref(), Expr::Get(_, j) if outputs.contains(j)) {
+                                scope.pointers.push(output.clone());
+                                addrs.push(output.clone());
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Some(res)
+    }
+
+    /// The cell an address refers to, if it is one of ours.
+    fn cell(&self, scope: &Scope, addr: &RcExpr) -> Option<usize> {
+        if scope.is_pointer(addr) {
+            return Some(0);
+        }
+        match addr.as_ref() {
+            Expr::Bop(BinaryOp::PtrAdd, ptr, offset) if scope.is_pointer(ptr) => {
+                match offset.as_ref() {
+                    Expr::Const(Constant::Int(k), _, _) => {
+                        usize::try_from(*k).ok().filter(|k| *k < self.size)
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Rebuilds the state-edge operations in `chain` without the allocation.
+    /// Returns the replacements and the values of the cells at the end.
+    fn rebuild(
+        &self,
+        scope: &Scope,
+        accesses: &Accesses,
+        chain: Vec<RcExpr>,
+        parents: &IndexMap<*const Expr, Vec<RcExpr>>,
+    ) -> Option<(IndexMap<*const Expr, RcExpr>, Vec<RcExpr>)> {
+        let on_chain = chain.iter().map(Rc::as_ptr).collect::<IndexSet<_>>();
+        if !accesses.ops.is_subset(&on_chain)
+            || !accesses.regions.keys().all(|r| on_chain.contains(r))
+        {
+            return None;
+        }
+
+        let mut replacements = IndexMap::new();
+        let mut cache = IndexMap::new();
+        if let Some(state_in) = &scope.state_in {
+            for state in &scope.states {
+                replacements.insert(Rc::as_ptr(state), state_in.clone());
+            }
+        }
+        let mut values = scope.values.clone();
+        for node in chain {
+            if let Some(k) = accesses.regions.get(&Rc::as_ptr(&node)) {
+                let (new, outputs) =
+                    self.rebuild_region(scope, &node, *k, &values, &replacements, parents)?;
+                values = (0..self.size)
+                    .map(|c| get(new.clone(), outputs + c))
+                    .collect();
+                replacements.insert(Rc::as_ptr(&node), new);
+                continue;
+            }
+            let (Expr::Top(TernaryOp::Write, _, _, prev)
+            | Expr::Bop(BinaryOp::Load | BinaryOp::Print | BinaryOp::Free, _, prev)
+            | Expr::Alloc(_, _, prev, _)) = node.as_ref()
+            else {
+                // a region or call that doesn't see the pointer
+                let new = rewrite(&node, &replacements, &mut cache);
+                replacements.insert(Rc::as_ptr(&node), new);
+                continue;
+            };
+            let cur = rewrite(prev, &replacements, &mut cache);
+            let new = match node.as_ref() {
+                Expr::Top(TernaryOp::Write, addr, val, _) => {
+                    let val = rewrite(val, &replacements, &mut cache);
+                    if let Some(cell) = self.cell(scope, addr) {
+                        values[cell] = val;
+                        cur
+                    } else {
+                        write(rewrite(addr, &replacements, &mut cache), val, cur)
+                    }
+                }
+                Expr::Bop(BinaryOp::Load, addr, _) => {
+                    if let Some(cell) = self.cell(scope, addr) {
+                        for parent in parents.get(&Rc::as_ptr(&node)).into_iter().flatten() {
+                            let replacement = match parent.as_ref() {
+                                Expr::Get(_, 0) => values[cell].clone(),
+                                Expr::Get(_, 1) => cur.clone(),
+                                _ => return None,
+                            };
+                            replacements.insert(Rc::as_ptr(parent), replacement);
+                        }
+                        continue;
+                    }
+                    load(rewrite(addr, &replacements, &mut cache), cur)
+                }
+                Expr::Bop(BinaryOp::Print, val, _) => {
+                    tprint(rewrite(val, &replacements, &mut cache), cur)
+                }
+                Expr::Bop(BinaryOp::Free, ptr, _) => {
+                    free(rewrite(ptr, &replacements, &mut cache), cur)
+                }
+                Expr::Alloc(id, amount, _, ty) => alloc(
+                    *id,
+                    rewrite(amount, &replacements, &mut cache),
+                    cur,
+                    ty.clone(),
+                ),
+                _ => unreachable!(),
+            };
+            replacements.insert(Rc::as_ptr(&node), new);
+        }
+        Some((replacements, values))
+    }
+
+    /// Rebuilds a region that gets the pointer as its input `k`.
+    /// The pointer input becomes a dead integer, and the cells are passed in
+    /// and out after the other values.
+    /// Returns the new region and its number of outputs before the cells.
+    fn rebuild_region(
+        &self,
+        scope: &Scope,
+        region: &RcExpr,
+        k: usize,
+        values: &[RcExpr],
+        replacements: &IndexMap<*const Expr, RcExpr>,
+        parents: &IndexMap<*const Expr, Vec<RcExpr>>,
+    ) -> Option<(RcExpr, usize)> {
+        let mut cache = IndexMap::new();
+        let (inputs, bodies) = region_parts(region)?;
+        let inputs = try_split_inputs(inputs)?;
+        let (_, state_index) = scope.longest_chain(&inputs, &mut IndexMap::new())?;
+        let new_inputs = inputs
+            .iter()
+            .enumerate()
+            .map(|(m, input)| {
+                if m == k {
+                    int(0)
+                } else {
+                    rewrite(input, replacements, &mut cache)
+                }
+            })
+            .chain(values.iter().cloned())
+            .collect::<Vec<_>>();
+
+        let mut new_bodies = vec![];
+        let mut num_outputs = 0;
+        for body in bodies {
+            let mut inner = Scope {
+                pointers: arg_gets(&body, k),
+                states: arg_gets(&body, state_index),
+                state_in: None,
+                region: Some(region.clone()),
+                values: (0..self.size).map(|c| getat(inputs.len() + c)).collect(),
+            };
+            let accesses = self.accesses(&mut inner, parents)?;
+            if !accesses.frees.is_empty() {
+                return None;
+            }
+            let outputs = try_split_inputs(body)?;
+            let skip = usize::from(matches!(region.as_ref(), Expr::DoWhile(..)));
+            let (chain, _) = inner.longest_chain(&outputs[skip..], &mut IndexMap::new())?;
+            let (inner_replacements, inner_values) =
+                self.rebuild(&inner, &accesses, chain, parents)?;
+            let mut inner_cache = IndexMap::new();
+            num_outputs = outputs.len() - skip;
+            new_bodies.push(parallel_vec(
+                outputs
+                    .iter()
+                    .map(|output| rewrite(output, &inner_replacements, &mut inner_cache))
+                    .chain(inner_values),
+            ));
+        }
+
+        let new_inputs = parallel_vec(new_inputs);
+        let new = match region.as_ref() {
+            Expr::DoWhile(..) => dowhile(new_inputs, new_bodies.pop()?),
+            Expr::If(pred, ..) => {
+                let els = new_bodies.pop()?;
+                let thn = new_bodies.pop()?;
+                tif(
+                    rewrite(pred, replacements, &mut cache),
+                    new_inputs,
+                    thn,
+                    els,
+                )
+            }
+            Expr::Switch(pred, ..) => switch_vec(
+                rewrite(pred, replacements, &mut cache),
+                new_inputs,
+                new_bodies,
+            ),
+            _ => unreachable!(),
+        };
+        Some((new, num_outputs))
+    }
+}
+
+impl Scope {
+    fn is_pointer(&self, expr: &RcExpr) -> bool {
+        self.pointers.iter().any(|ptr| Rc::ptr_eq(ptr, expr))
+    }
+
+    /// Of the chains that lead to `ends`, the longest one and the index of its end.
+    /// The other chains must be prefixes of it.
+    fn longest_chain(
+        &self,
+        ends: &[RcExpr],
+        memo: &mut IndexMap<*const Expr, Option<Vec<RcExpr>>>,
+    ) -> Option<(Vec<RcExpr>, usize)> {
+        // pointers are never on the state edge, but may come out of regions on it
+        let chains = ends
+            .iter()
+            .enumerate()
+            .filter(|(_, end)| !self.is_pointer(end))
+            .filter_map(|(i, end)| Some((self.chain_to(end, memo)?, i)))
+            .collect::<Vec<_>>();
+        let (longest, index) = chains.iter().max_by_key(|(chain, _)| chain.len())?;
+        chains
+            .iter()
+            .all(|(chain, _)| {
+                chain
+                    .iter()
+                    .zip(longest)
+                    .all(|(op, other)| Rc::ptr_eq(op, other))
+            })
+            .then(|| (longest.clone(), *index))
+    }
+
+    /// The state-edge operations from the start of the scope to `cur`, in order.
+    /// Regions and calls are stepped over through their state input.
+    /// Regions that get the pointer are rebuilt, the others can't touch its cells.
+    fn chain_to(
+        &self,
+        cur: &RcExpr,
+        memo: &mut IndexMap<*const Expr, Option<Vec<RcExpr>>>,
+    ) -> Option<Vec<RcExpr>> {
+        if self.states.iter().any(|state| Rc::ptr_eq(state, cur)) {
+            return Some(vec![]);
+        }
+        if let Some(res) = memo.get(&Rc::as_ptr(cur)) {
+            return res.clone();
+        }
+        let res = match cur.as_ref() {
+            Expr::Top(TernaryOp::Write, _, _, prev)
+            | Expr::Bop(BinaryOp::Print | BinaryOp::Free, _, prev) => {
+                self.chain_to(prev, memo).map(|chain| (chain, cur.clone()))
+            }
+            Expr::Get(node, index) => match (node.as_ref(), index) {
+                (Expr::Bop(BinaryOp::Load, _, prev) | Expr::Alloc(_, _, prev, _), 1) => {
+                    self.chain_to(prev, memo).map(|chain| (chain, node.clone()))
+                }
+                (
+                    Expr::If(_, inputs, _, _)
+                    | Expr::Switch(_, inputs, _)
+                    | Expr::DoWhile(inputs, _)
+                    | Expr::Call(_, inputs),
+                    _,
+                ) => {
+                    // Other outputs of earlier regions can lead back too,
+                    // but only the state input sees all of the operations.
+                    let inputs = try_split_inputs(inputs.clone())?;
+                    self.longest_chain(&inputs, memo)
+                        .map(|(chain, _)| (chain, node.clone()))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+        .map(|(mut chain, node)| {
+            chain.push(node);
+            chain
+        });
+        memo.insert(Rc::as_ptr(cur), res.clone());
+        res
+    }
+}
+
+/// Replaces one allocation in the body, if any qualifies.
+fn scalar_replace_one(body: &RcExpr) -> Option<RcExpr> {
+    let parents = parents(body);
+    let mut seen = IndexSet::new();
+    let mut todo = vec![body.clone()];
+    while let Some(expr) = todo.pop() {
+        if !seen.insert(Rc::as_ptr(&expr)) {
+            continue;
+        }
+        if let Some(replacements) = Candidate::new(&expr, &parents)
+            .and_then(|(candidate, scope)| candidate.replacements(scope, &parents))
+        {
+            return Some(rewrite(body, &replacements, &mut IndexMap::new()));
+        }
+        todo.extend(expr.children_exprs());
+    }
+    None
+}
+
+/// Replaces small non-escaping allocations with values across the program.
+pub fn scalar_replace_allocations(program: &TreeProgram) -> TreeProgram {
+    let mut res = program.clone();
+    let mut changed = false;
+    for name in program.fns() {
+        let func = program.get_function(&name).unwrap();
+        let mut body = func.func_body().unwrap().clone();
+        let mut replaced = false;
+        while let Some(new_body) = scalar_replace_one(&body) {
+            body = new_body;
+            replaced = true;
+        }
+        if replaced {
+            res.replace_fn(
+                &name,
+                Rc::new(Expr::Function(
+                    name.clone(),
+                    func.func_input_ty().unwrap(),
+                    func.func_output_ty().unwrap(),
+                    body,
+                )),
+            );
+            changed = true;
+        }
+    }
+    if changed {
+        res.override_arg_types()
+    } else {
+        res
+    }
+}
+
+#[cfg(test)]
+fn allocs_in_program(program: &TreeProgram) -> usize {
+    program
+        .fns()
+        .iter()
+        .map(|name| {
+            let body = program.get_function(name).unwrap().func_body().unwrap();
+            parents(body)
+                .values()
+                .flatten()
+                .filter(|parent| matches!(parent.as_ref(), Expr::Alloc(..)))
+                .map(Rc::as_ptr)
+                .collect::<IndexSet<_>>()
+                .len()
+        })
+        .sum()
+}
+
+#[test]
+fn test_scalar_replace_two_cells() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    // p = alloc(2); p[0] = x; p[1] = x + 1; print p[1]; res = p[0] + p[1]; free p
+    let p_and_state = alloc(0, int(2), getat(1), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let q = ptradd(p.clone(), int(1));
+    let state = write(p.clone(), getat(0), get(p_and_state, 1));
+    let state = write(q.clone(), add(getat(0), int(1)), state);
+    let second = load(q.clone(), state);
+    let state = tprint(get(second.clone(), 0), get(second.clone(), 1));
+    let first = load(p.clone(), state);
+    let sum = add(get(first.clone(), 0), get(second, 0));
+    let prog = program!(function(
+        "main",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(sum, free(p, get(first, 1)))
+    ),)
+    .with_arg_types();
+
+    let replaced = scalar_replace_allocations(&prog);
+    assert_eq!(allocs_in_program(&prog), 1);
+    assert_eq!(allocs_in_program(&replaced), 0);
+    assert_eq!(
+        interpret_dag_prog(&prog, &tuplev!(intv(5), statev())),
+        interpret_dag_prog(&replaced, &tuplev!(intv(5), statev()))
+    );
+}
+
+#[test]
+fn test_scalar_replace_across_if() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    // p = alloc(1); *p = x; if x < 3 { print x }; res = *p; free p
+    let p_and_state = alloc(0, int(1), getat(1), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let state = write(p.clone(), getat(0), get(p_and_state, 1));
+    let branch = tif(
+        less_than(getat(0), int(3)),
+        parallel!(getat(0), state),
+        single(tprint(getat(0), getat(1))),
+        single(getat(1)),
+    );
+    let val = load(p.clone(), get(branch, 0));
+    let prog = program!(function(
+        "main",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(get(val.clone(), 0), free(p, get(val, 1)))
+    ),)
+    .with_arg_types();
+
+    let replaced = scalar_replace_allocations(&prog);
+    assert_eq!(allocs_in_program(&prog), 1);
+    assert_eq!(allocs_in_program(&replaced), 0);
+    for x in [1, 5] {
+        assert_eq!(
+            interpret_dag_prog(&prog, &tuplev!(intv(x), statev())),
+            interpret_dag_prog(&replaced, &tuplev!(intv(x), statev()))
+        );
+    }
+}
+
+#[test]
+fn test_scalar_replace_pointer_passed_to_if() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    // p = alloc(1); *p = 7; if x < 3 { *p = 1 }; res = *p; free p
+    let p_and_state = alloc(0, int(1), getat(1), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let state = write(p.clone(), int(7), get(p_and_state, 1));
+    let branch = tif(
+        less_than(getat(0), int(3)),
+        parallel!(p.clone(), state),
+        single(write(getat(0), int(1), getat(1))),
+        single(getat(1)),
+    );
+    let val = load(p.clone(), get(branch, 0));
+    let prog = program!(function(
+        "main",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(get(val.clone(), 0), free(p, get(val, 1)))
+    ),)
+    .with_arg_types();
+
+    let replaced = scalar_replace_allocations(&prog);
+    assert_eq!(allocs_in_program(&prog), 1);
+    assert_eq!(allocs_in_program(&replaced), 0);
+    for x in [1, 5] {
+        assert_eq!(
+            interpret_dag_prog(&prog, &tuplev!(intv(x), statev())),
+            interpret_dag_prog(&replaced, &tuplev!(intv(x), statev()))
+        );
+    }
+}
+
+#[test]
+fn test_scalar_replace_pointer_passed_to_loop() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    // p = alloc(1); *p = 0; i = 0
+    // do { *p = *p + i; print *p; i = i + 1 } while i < x
+    // res = *p; free p
+    let p_and_state = alloc(0, int(1), getat(1), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let init = write(p.clone(), int(0), get(p_and_state, 1));
+    let cur = load(getat(1), getat(2));
+    let state = write(getat(1), add(get(cur.clone(), 0), getat(0)), get(cur, 1));
+    let printed = load(getat(1), state);
+    let state = tprint(get(printed.clone(), 0), get(printed, 1));
+    let next = add(getat(0), int(1));
+    let lp = dowhile(
+        parallel!(int(0), p.clone(), init, getat(0)),
+        parallel!(
+            less_than(next.clone(), getat(3)),
+            next,
+            getat(1),
+            state,
+            getat(3)
+        ),
+    );
+    let val = load(get(lp.clone(), 1), get(lp, 2));
+    let prog = program!(function(
+        "main",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(get(val.clone(), 0), free(p, get(val, 1)))
+    ),)
+    .with_arg_types();
+
+    let replaced = scalar_replace_allocations(&prog);
+    assert_eq!(allocs_in_program(&prog), 1);
+    assert_eq!(allocs_in_program(&replaced), 0);
+    for x in [1, 4] {
+        assert_eq!(
+            interpret_dag_prog(&prog, &tuplev!(intv(x), statev())),
+            interpret_dag_prog(&replaced, &tuplev!(intv(x), statev()))
+        );
+    }
+}
+
+#[test]
+fn test_scalar_replace_skips_pointer_moved_in_loop() {
+    use crate::ast::*;
+    // p = alloc(2); do { *p = 1; p = p + 1 } while false; free p
+    let p_and_state = alloc(0, int(2), getat(1), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let lp = dowhile(
+        parallel!(p.clone(), get(p_and_state, 1)),
+        parallel!(
+            tfalse(),
+            ptradd(getat(0), int(1)),
+            write(getat(0), int(1), getat(1))
+        ),
+    );
+    let prog = program!(function(
+        "main",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(getat(0), free(p, get(lp, 1)))
+    ),)
+    .with_arg_types();
+
+    assert_eq!(scalar_replace_allocations(&prog), prog);
+}
+
+#[test]
+fn test_scalar_replace_skips_variable_offsets() {
+    use crate::ast::*;
+    // p = alloc(2); p[0] = 1; p[1] = 2; res = p[x]; free p
+    let p_and_state = alloc(0, int(2), getat(1), pointert(intt()));
+    let p = get(p_and_state.clone(), 0);
+    let state = write(p.clone(), int(1), get(p_and_state, 1));
+    let state = write(ptradd(p.clone(), int(1)), int(2), state);
+    let val = load(ptradd(p.clone(), getat(0)), state);
+    let prog = program!(function(
+        "main",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(get(val.clone(), 0), free(p, get(val, 1)))
+    ),)
+    .with_arg_types();
+
+    assert_eq!(scalar_replace_allocations(&prog), prog);
+}
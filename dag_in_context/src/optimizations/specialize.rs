@@ -0,0 +1,404 @@
+//! Interprocedural constant propagation.
+//! Call sites that pass constants to a function are redirected to a specialized
+//! clone of the function with the constants substituted in.
+//! The clones are optimized in the same e-graph as the rest of the program.
+//!
+//! Also computes bounds on function arguments that hold at every call site,
+//! which are given to the interval analysis as `ArgLoBound` and `ArgHiBound` facts.
+
+use std::{fmt::Write, rc::Rc};
+
+use indexmap::IndexMap;
+
+use crate::{
+    config::{MAX_SPECIALIZATIONS_PER_FUNCTION, MAX_SPECIALIZED_FUNCTION_SIZE},
+    optimizations::{call_graph::call_sites, function_inlining::tree_size},
+    remove_dead_code_nodes::try_split_inputs,
+    schema::{BinaryOp, Constant, Expr, RcExpr, TernaryOp, TreeProgram},
+};
+
+/// For each argument of a call, the constant passed in (if any).
+type ConstPattern = Vec<Option<Constant>>;
+
+/// Bounds on an integer expression that hold no matter what
+/// the arguments of the enclosing region are.
+/// These stay valid when the expression is substituted into another context.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct StaticBounds {
+    lo: Option<i64>,
+    hi: Option<i64>,
+}
+
+impl StaticBounds {
+    const UNKNOWN: StaticBounds = StaticBounds { lo: None, hi: None };
+
+    /// Both bounds, or neither when one is missing.
+    fn exact(lo: Option<i64>, hi: Option<i64>) -> StaticBounds {
+        match (lo, hi) {
+            (Some(_), Some(_)) => StaticBounds { lo, hi },
+            _ => StaticBounds::UNKNOWN,
+        }
+    }
+
+    fn union(self, other: StaticBounds) -> StaticBounds {
+        let both = |a: Option<i64>, b: Option<i64>, f: fn(i64, i64) -> i64| Some(f(a?, b?));
+        StaticBounds {
+            lo: both(self.lo, other.lo, i64::min),
+            hi: both(self.hi, other.hi, i64::max),
+        }
+    }
+}
+
+fn static_bounds(expr: &RcExpr) -> StaticBounds {
+    let both = |a: Option<i64>, b: Option<i64>, f: fn(i64, i64) -> Option<i64>| f(a?, b?);
+    match expr.as_ref() {
+        Expr::Const(Constant::Int(c), _, _) => StaticBounds {
+            lo: Some(*c),
+            hi: Some(*c),
+        },
+        Expr::Bop(op, a, b) => {
+            let (a, b) = (static_bounds(a), static_bounds(b));
+            match op {
+                // integers wrap, so the bounds only hold when neither end overflows
+                BinaryOp::Add => StaticBounds::exact(
+                    both(a.lo, b.lo, i64::checked_add),
+                    both(a.hi, b.hi, i64::checked_add),
+                ),
+                BinaryOp::Sub => StaticBounds::exact(
+                    both(a.lo, b.hi, i64::checked_sub),
+                    both(a.hi, b.lo, i64::checked_sub),
+                ),
+                BinaryOp::Smin => StaticBounds {
+                    lo: both(a.lo, b.lo, |x, y| Some(x.min(y))),
+                    hi: match (a.hi, b.hi) {
+                        (Some(x), Some(y)) => Some(x.min(y)),
+                        (x, y) => x.or(y),
+                    },
+                },
+                BinaryOp::Smax => StaticBounds {
+                    lo: match (a.lo, b.lo) {
+                        (Some(x), Some(y)) => Some(x.max(y)),
+                        (x, y) => x.or(y),
+                    },
+                    hi: both(a.hi, b.hi, |x, y| Some(x.max(y))),
+                },
+                // masking with a non-negative value gives a value between 0 and the mask
+                BinaryOp::Bitand => {
+                    let non_negative_hi = |bounds: StaticBounds| match bounds {
+                        StaticBounds {
+                            lo: Some(lo),
+                            hi: Some(hi),
+                        } if lo >= 0 => Some(hi),
+                        _ => None,
+                    };
+                    match (non_negative_hi(a), non_negative_hi(b)) {
+                        (None, None) => StaticBounds::UNKNOWN,
+                        (x, y) => StaticBounds {
+                            lo: Some(0),
+                            hi: x.into_iter().chain(y).min(),
+                        },
+                    }
+                }
+                _ => StaticBounds::UNKNOWN,
+            }
+        }
+        Expr::Top(TernaryOp::Select, _pred, thn, els) => {
+            static_bounds(thn).union(static_bounds(els))
+        }
+        _ => StaticBounds::UNKNOWN,
+    }
+}
+
+/// Returns the arguments of a call, if they are an explicit tuple.
+fn call_args(call: &RcExpr) -> Option<(String, Vec<RcExpr>)> {
+    let Expr::Call(name, args) = call.as_ref() else {
+        panic!("Expected call, got {:?}", call);
+    };
+    Some((name.clone(), try_split_inputs(args.clone())?))
+}
+
+/// Replaces reads of constant arguments in the top-level scope of `expr`.
+/// Nested regions have their own arguments, so they are left alone.
+fn subst_const_args(
+    expr: &RcExpr,
+    consts: &IndexMap<usize, Constant>,
+    cache: &mut IndexMap<*const Expr, RcExpr>,
+) -> RcExpr {
+    if let Some(res) = cache.get(&Rc::as_ptr(expr)) {
+        return res.clone();
+    }
+    let res = match expr.as_ref() {
+        Expr::Get(arg, i) if consts.contains_key(i) && matches!(arg.as_ref(), Expr::Arg(..)) => {
+            let Expr::Arg(ty, ctx) = arg.as_ref() else {
+                unreachable!()
+            };
+            Rc::new(Expr::Const(consts[i].clone(), ty.clone(), ctx.clone()))
+        }
+        Expr::If(pred, inputs, thn, els) => Rc::new(Expr::If(
+            subst_const_args(pred, consts, cache),
+            subst_const_args(inputs, consts, cache),
+            thn.clone(),
+            els.clone(),
+        )),
+        Expr::Switch(pred, inputs, branches) => Rc::new(Expr::Switch(
+            subst_const_args(pred, consts, cache),
+            subst_const_args(inputs, consts, cache),
+            branches.clone(),
+        )),
+        Expr::DoWhile(inputs, body) => Rc::new(Expr::DoWhile(
+            subst_const_args(inputs, consts, cache),
+            body.clone(),
+        )),
+        _ => expr.map_expr_children(|child| subst_const_args(child, consts, cache)),
+    };
+    cache.insert(Rc::as_ptr(expr), res.clone());
+    res
+}
+
+fn fresh_name(program: &TreeProgram, base: &str) -> String {
+    let existing = program.fns();
+    (0..)
+        .map(|i| format!("{base}_spec{i}"))
+        .find(|name| !existing.contains(name))
+        .unwrap()
+}
+
+fn redirect_calls(
+    expr: &RcExpr,
+    redirects: &IndexMap<*const Expr, String>,
+    cache: &mut IndexMap<*const Expr, RcExpr>,
+) -> RcExpr {
+    if let Some(res) = cache.get(&Rc::as_ptr(expr)) {
+        return res.clone();
+    }
+    let res = match expr.as_ref() {
+        Expr::Call(name, args) => {
+            let new_name = redirects.get(&Rc::as_ptr(expr)).unwrap_or(name);
+            Rc::new(Expr::Call(
+                new_name.clone(),
+                redirect_calls(args, redirects, cache),
+            ))
+        }
+        _ => expr.map_expr_children(|child| redirect_calls(child, redirects, cache)),
+    };
+    cache.insert(Rc::as_ptr(expr), res.clone());
+    res
+}
+
+/// Creates specialized clones of functions that are called with constant arguments,
+/// and redirects those calls to the clones.
+/// At most `MAX_SPECIALIZATIONS_PER_FUNCTION` clones are made per function,
+/// preferring the constant patterns with the most call sites,
+/// and functions larger than `MAX_SPECIALIZED_FUNCTION_SIZE` are not cloned.
+pub fn specialize_functions(program: &TreeProgram) -> TreeProgram {
+    let entry_name = program.entry.func_name().unwrap();
+    let mut res = program.clone();
+    let mut redirects: IndexMap<*const Expr, String> = IndexMap::new();
+
+    for (callee, calls) in call_sites(program) {
+        if callee == entry_name {
+            continue;
+        }
+        let func = program.get_function(&callee).unwrap().clone();
+        let func_body = func.func_body().unwrap();
+        if tree_size(func_body) > MAX_SPECIALIZED_FUNCTION_SIZE {
+            continue;
+        }
+        let mut patterns: IndexMap<ConstPattern, Vec<RcExpr>> = IndexMap::new();
+        for call in calls {
+            let Some((_, args)) = call_args(&call) else {
+                continue;
+            };
+            let pattern: ConstPattern = args
+                .iter()
+                .map(|arg| match arg.as_ref() {
+                    Expr::Const(c, _, _) => Some(c.clone()),
+                    _ => None,
+                })
+                .collect();
+            if pattern.iter().any(Option::is_some) {
+                patterns.entry(pattern).or_default().push(call);
+            }
+        }
+        patterns.sort_by(|_, a, _, b| b.len().cmp(&a.len()));
+
+        for (pattern, calls) in patterns.into_iter().take(MAX_SPECIALIZATIONS_PER_FUNCTION) {
+            let consts: IndexMap<usize, Constant> = pattern
+                .into_iter()
+                .enumerate()
+                .filter_map(|(i, c)| Some((i, c?)))
+                .collect();
+            let body = subst_const_args(func_body, &consts, &mut IndexMap::new());
+            // the constants are never read, for example because `callee` is
+            // already a clone from an earlier pass
+            if body == *func_body {
+                continue;
+            }
+            let name = fresh_name(&res, &callee);
+            log::info!("Specializing {callee} as {name} with constants {consts:?}");
+            res.functions.push(Rc::new(Expr::Function(
+                name.clone(),
+                func.func_input_ty().unwrap(),
+                func.func_output_ty().unwrap(),
+                body,
+            )));
+            for call in calls {
+                redirects.insert(Rc::as_ptr(&call), name.clone());
+            }
+        }
+    }
+
+    if redirects.is_empty() {
+        return program.clone();
+    }
+
+    let mut cache = IndexMap::new();
+    let mut redirect_fn =
+        |func: &RcExpr| func.map_expr_children(|body| redirect_calls(body, &redirects, &mut cache));
+    res.entry = redirect_fn(&res.entry);
+    res.functions = res.functions.iter().map(redirect_fn).collect();
+    res.override_arg_types()
+}
+
+/// Finds bounds on integer arguments of functions that hold at every call site.
+/// Returns (function name, argument index, lower bound, upper bound).
+/// Only bounds that don't depend on the caller's arguments are used,
+/// so they remain valid as the call sites are rewritten.
+fn arg_bounds(program: &TreeProgram) -> Vec<(String, usize, StaticBounds)> {
+    let entry_name = program.entry.func_name().unwrap();
+    let mut res = vec![];
+    for (callee, calls) in call_sites(program) {
+        // the entry function is also called by the outside world
+        if callee == entry_name {
+            continue;
+        }
+        let mut bounds: Option<Vec<StaticBounds>> = None;
+        for call in &calls {
+            let Some((_, args)) = call_args(call) else {
+                bounds = None;
+                break;
+            };
+            let call_bounds: Vec<StaticBounds> = args.iter().map(static_bounds).collect();
+            bounds = Some(match bounds {
+                None => call_bounds,
+                Some(prev) => prev
+                    .into_iter()
+                    .zip(call_bounds)
+                    .map(|(a, b)| a.union(b))
+                    .collect(),
+            });
+        }
+        for (i, bound) in bounds.into_iter().flatten().enumerate() {
+            if bound != StaticBounds::UNKNOWN {
+                res.push((callee.clone(), i, bound));
+            }
+        }
+    }
+    res
+}
+
+/// Egglog facts for `arg_bounds`, used by `interval_analysis.egg`.
+pub(crate) fn arg_bound_facts(program: &TreeProgram) -> String {
+    let mut res = String::new();
+    for (func, i, bounds) in arg_bounds(program) {
+        if let Some(lo) = bounds.lo {
+            writeln!(res, "(ArgLoBound \"{func}\" {i} {lo})").unwrap();
+        }
+        if let Some(hi) = bounds.hi {
+            writeln!(res, "(ArgHiBound \"{func}\" {i} {hi})").unwrap();
+        }
+    }
+    res
+}
+
+#[test]
+fn test_specialize_constant_args() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    let prog = program!(
+        function(
+            "main",
+            base(intt()),
+            base(intt()),
+            add(
+                call("scale", parallel!(arg(), int(4))),
+                call("scale", parallel!(int(2), arg()))
+            )
+        ),
+        function(
+            "scale",
+            tuplet!(intt(), intt()),
+            base(intt()),
+            mul(getat(0), getat(1))
+        ),
+    );
+    let specialized = specialize_functions(&prog);
+
+    assert_eq!(specialized.fns().len(), 4);
+    let spec0 = specialized.get_function("scale_spec0").unwrap();
+    assert!(matches!(
+        spec0.func_body().unwrap().as_ref(),
+        Expr::Bop(BinaryOp::Mul, _, rhs) if matches!(rhs.as_ref(), Expr::Const(Constant::Int(4), _, _))
+    ));
+    assert_eq!(
+        interpret_dag_prog(&prog, &intv(5)),
+        interpret_dag_prog(&specialized, &intv(5))
+    );
+}
+
+#[test]
+fn test_specialize_respects_size_and_is_idempotent() {
+    use crate::ast::*;
+    let big_body = (0..MAX_SPECIALIZED_FUNCTION_SIZE).fold(getat(0), |acc, _| add(acc, getat(1)));
+    let prog = program!(
+        function(
+            "main",
+            base(intt()),
+            base(intt()),
+            add(
+                call("scale", parallel!(arg(), int(4))),
+                call("big", parallel!(arg(), int(4)))
+            )
+        ),
+        function(
+            "scale",
+            tuplet!(intt(), intt()),
+            base(intt()),
+            mul(getat(0), getat(1))
+        ),
+        function("big", tuplet!(intt(), intt()), base(intt()), big_body),
+    );
+    let specialized = specialize_functions(&prog);
+    assert!(specialized.get_function("scale_spec0").is_some());
+    assert!(specialized.get_function("big_spec0").is_none());
+
+    // the clone still receives the constant, but doesn't read it
+    let again = specialize_functions(&specialized);
+    assert_eq!(again.fns(), specialized.fns());
+}
+
+#[test]
+fn test_arg_bounds_from_all_call_sites() {
+    use crate::ast::*;
+    let prog = program!(
+        function(
+            "main",
+            base(intt()),
+            base(intt()),
+            add(
+                call("index", parallel!(bitand(arg(), int(7)))),
+                call("index", parallel!(int(12)))
+            )
+        ),
+        function(
+            "index",
+            tuplet!(intt()),
+            base(intt()),
+            add(getat(0), int(1))
+        ),
+    );
+    assert_eq!(
+        arg_bound_facts(&prog),
+        "(ArgLoBound \"index\" 0 0)\n(ArgHiBound \"index\" 0 12)\n"
+    );
+}
@@ -125,3 +125,155 @@ fn single_branch_switch() -> crate::Result {
         vec![],
     )
 }
+
+#[test]
+fn if_chain_to_switch() -> crate::Result {
+    use crate::ast::*;
+
+    // if x == 0 {10} else if x == 1 {20} else if x == 2 {30} else {40}
+    let innermost = tif(
+        eq(getat(0), int(2)),
+        arg(),
+        single(int(30)),
+        single(int(40)),
+    );
+    let inner = tif(eq(getat(0), int(1)), arg(), single(int(20)), innermost);
+    let chain = get(
+        tif(eq(arg(), int(0)), single(arg()), single(int(10)), inner),
+        0,
+    );
+
+    let prog = function("main", base(intt()), base(intt()), chain)
+        .func_with_arg_types()
+        .to_program(base(intt()), base(intt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!(
+            "(check (= {term} (Get (Switch pred inputs (Cons b0 (Cons b1 (Cons b2 (Cons b3 (Nil)))))) 0)))
+(check (DenseSwitch switch 0 0 3))"
+        ),
+        vec![with_context],
+        intv(2),
+        intv(30),
+        vec![],
+    )
+}
+
+#[test]
+fn identical_branches() -> crate::Result {
+    use crate::ast::*;
+
+    // if x < 0 {x + 1} else {x + 1}
+    let body = get(
+        tif(
+            less_than(arg(), int(0)),
+            single(arg()),
+            single(add(getat(0), int(1))),
+            single(add(getat(0), int(1))),
+        ),
+        0,
+    );
+    let prog = function("main", base(intt()), base(intt()), body)
+        .func_with_arg_types()
+        .to_program(base(intt()), base(intt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    let expected = function("main", base(intt()), base(intt()), add(arg(), int(1)))
+        .func_with_arg_types()
+        .to_program(base(intt()), base(intt()));
+    let (expected, expected_cache) = expected.add_context();
+    let expected_term = expected.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!(
+            "{expected}\n{}\n(check (= {term} {expected_term}))",
+            expected_cache.get_unions()
+        ),
+        vec![with_context],
+        intv(-5),
+        intv(-4),
+        vec![],
+    )
+}
+
+#[test]
+fn known_switch_pred_by_default() -> crate::Result {
+    use crate::ast::*;
+
+    // switch 1 [x + 10, x + 20] => x + 20
+    let body = get(
+        switch_vec(
+            int(1),
+            single(arg()),
+            vec![
+                single(add(getat(0), int(10))),
+                single(add(getat(0), int(20))),
+            ],
+        ),
+        0,
+    );
+    let prog = function("main", base(intt()), base(intt()), body)
+        .func_with_arg_types()
+        .to_program(base(intt()), base(intt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    let expected = function("main", base(intt()), base(intt()), add(arg(), int(20)))
+        .func_with_arg_types()
+        .to_program(base(intt()), base(intt()));
+    let (expected, expected_cache) = expected.add_context();
+    let expected_term = expected.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!(
+            "{expected}\n{}\n(check (= {term} {expected_term}))",
+            expected_cache.get_unions()
+        ),
+        vec![with_context],
+        intv(1),
+        intv(21),
+        vec![],
+    )
+}
+
+#[test]
+fn duplicate_switch_branches() -> crate::Result {
+    use crate::ast::*;
+
+    // if x == 0 {10} else if x == 1 {20} else if x == 2 {10} else {40}
+    // becomes a switch with one branch for both 0 and 2
+    let innermost = tif(
+        eq(getat(0), int(2)),
+        arg(),
+        single(int(10)),
+        single(int(40)),
+    );
+    let inner = tif(eq(getat(0), int(1)), arg(), single(int(20)), innermost);
+    let chain = get(
+        tif(eq(arg(), int(0)), single(arg()), single(int(10)), inner),
+        0,
+    );
+
+    let prog = function("main", base(intt()), base(intt()), chain)
+        .func_with_arg_types()
+        .to_program(base(intt()), base(intt()));
+    let (with_context, cache) = prog.add_context();
+    let term = with_context.entry.func_body().unwrap();
+
+    egglog_test(
+        &format!("{with_context}\n{}", cache.get_unions()),
+        &format!(
+            "(check (= {term} (Get (Switch pred inputs (Cons b0 (Cons b1 (Cons b2 (Nil))))) 0)))"
+        ),
+        vec![with_context],
+        intv(2),
+        intv(10),
+        vec![],
+    )
+}
@@ -0,0 +1,460 @@
+//! Turns tail-recursive functions into loops.
+//! `rec_to_loop.egg` only matches a body that is exactly
+//! `If(pred, inputs, Call(name, rec_case), base_case)`.
+//! This pass handles self calls anywhere in a tree of `If` and `Switch` branches,
+//! and mutual tail recursion between two functions
+//! (by first inlining one of them into the other).
+//!
+//! The loop carries the non-state arguments, the non-state results and a single state edge.
+//! Each branch either continues the loop with new arguments or exits with a result,
+//! filling in the other half with placeholder constants.
+//! Because of this, functions with pointer arguments or results are not transformed.
+//!
+//! Tree recursion, where a branch combines the results of several recursive
+//! calls (like `fib(n - 1) + fib(n - 2)`), is not transformed here or in
+//! `rec_to_loop.egg`: turning it into a loop needs an explicit stack.
+//! Only one recursive call per branch is supported, either in tail position
+//! or combined with an accumulator by `rec_to_loop.egg`.
+
+use std::rc::Rc;
+
+use indexmap::{IndexMap, IndexSet};
+
+use crate::{
+    ast::{arg, float, int, parallel_vec, tfalse, ttrue},
+    optimizations::{
+        call_graph::{calls_in_expr, CallGraph},
+        function_inlining::subst_expr,
+    },
+    schema::{BaseType, Expr, RcExpr, TreeProgram, Type},
+};
+
+/// The signature of a function, split into state and non-state parts.
+struct Signature {
+    inputs: Vec<BaseType>,
+    input_state: usize,
+    outputs: Vec<BaseType>,
+    output_state: usize,
+}
+
+impl Signature {
+    /// Returns a signature when there is exactly one state edge in and out,
+    /// and we can make up placeholder values for everything else.
+    fn new(func: &RcExpr) -> Option<Signature> {
+        let split = |ty: Type| -> Option<(Vec<BaseType>, usize)> {
+            let Type::TupleT(tys) = ty else {
+                return None;
+            };
+            let mut states = (0..tys.len()).filter(|i| tys[*i] == BaseType::StateT);
+            let state = states.next()?;
+            let placeholders_exist = (0..tys.len())
+                .filter(|i| *i != state)
+                .all(|i| placeholder(&tys[i]).is_some());
+            if states.next().is_some() || !placeholders_exist {
+                return None;
+            }
+            Some((tys, state))
+        };
+        let (inputs, input_state) = split(func.func_input_ty()?)?;
+        let (outputs, output_state) = split(func.func_output_ty()?)?;
+        Some(Signature {
+            inputs,
+            input_state,
+            outputs,
+            output_state,
+        })
+    }
+
+    /// Index of the state edge in the loop's tuple.
+    fn loop_state(&self) -> usize {
+        self.inputs.len() + self.outputs.len() - 2
+    }
+
+    /// Builds the loop tuple: non-state arguments, then non-state results, then the state.
+    fn loop_tuple(
+        &self,
+        args: impl Fn(usize) -> RcExpr,
+        results: impl Fn(usize) -> RcExpr,
+        state: RcExpr,
+    ) -> Vec<RcExpr> {
+        let args = (0..self.inputs.len())
+            .filter(|i| *i != self.input_state)
+            .map(args);
+        let results = (0..self.outputs.len())
+            .filter(|i| *i != self.output_state)
+            .map(results);
+        args.chain(results).chain(std::iter::once(state)).collect()
+    }
+
+    /// Position of non-state element `i` of a tuple with the state at `state`.
+    fn non_state_position(i: usize, state: usize) -> usize {
+        if i > state {
+            i - 1
+        } else {
+            i
+        }
+    }
+}
+
+/// A constant standing in for a value that is never used.
+fn placeholder(ty: &BaseType) -> Option<RcExpr> {
+    match ty {
+        BaseType::IntT => Some(int(0)),
+        BaseType::BoolT => Some(tfalse()),
+        BaseType::FloatT => Some(float(0.0)),
+        BaseType::PointerT(_) | BaseType::StateT => None,
+    }
+}
+
+/// Finds the calls to `name` in tail position of `expr`: region roots
+/// reachable through the branches of `If` and `Switch`.
+fn tail_calls(expr: &RcExpr, name: &str, res: &mut IndexSet<*const Expr>) {
+    match expr.as_ref() {
+        Expr::Call(callee, _) if callee == name => {
+            res.insert(Rc::as_ptr(expr));
+        }
+        Expr::If(_, _, thn, els) => {
+            tail_calls(thn, name, res);
+            tail_calls(els, name, res);
+        }
+        Expr::Switch(_, _, branches) => {
+            for branch in branches {
+                tail_calls(branch, name, res);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// True when `func` calls itself, and only in tail position.
+fn is_tail_recursive(func: &RcExpr) -> bool {
+    let name = func.func_name().unwrap();
+    let body = func.func_body().unwrap();
+    let self_calls: Vec<RcExpr> = calls_in_expr(body)
+        .into_iter()
+        .filter(|call| matches!(call.as_ref(), Expr::Call(callee, _) if *callee == name))
+        .collect();
+    let mut tail = IndexSet::new();
+    tail_calls(body, &name, &mut tail);
+    !self_calls.is_empty()
+        && self_calls
+            .iter()
+            .all(|call| tail.contains(&Rc::as_ptr(call)))
+}
+
+/// Rewrites a region root so that it produces the loop's predicate and loop tuple.
+fn to_loop_step(expr: &RcExpr, name: &str, sig: &Signature) -> RcExpr {
+    let placeholders = |tys: &[BaseType]| {
+        let tys = tys.to_vec();
+        move |i: usize| placeholder(&tys[i]).unwrap()
+    };
+    match expr.as_ref() {
+        Expr::Call(callee, args) if callee == name => {
+            let tuple = sig.loop_tuple(
+                |i| Rc::new(Expr::Get(args.clone(), i)),
+                placeholders(&sig.outputs),
+                Rc::new(Expr::Get(args.clone(), sig.input_state)),
+            );
+            parallel_vec(std::iter::once(ttrue()).chain(tuple).collect::<Vec<_>>())
+        }
+        Expr::If(pred, inputs, thn, els) => Rc::new(Expr::If(
+            pred.clone(),
+            inputs.clone(),
+            to_loop_step(thn, name, sig),
+            to_loop_step(els, name, sig),
+        )),
+        Expr::Switch(pred, inputs, branches) => Rc::new(Expr::Switch(
+            pred.clone(),
+            inputs.clone(),
+            branches
+                .iter()
+                .map(|branch| to_loop_step(branch, name, sig))
+                .collect(),
+        )),
+        _ => {
+            let tuple = sig.loop_tuple(
+                placeholders(&sig.inputs),
+                |i| Rc::new(Expr::Get(expr.clone(), i)),
+                Rc::new(Expr::Get(expr.clone(), sig.output_state)),
+            );
+            parallel_vec(std::iter::once(tfalse()).chain(tuple).collect::<Vec<_>>())
+        }
+    }
+}
+
+/// Turns a tail-recursive function into a loop, if its signature allows it.
+fn tail_recursion_to_loop(func: &RcExpr) -> Option<RcExpr> {
+    if !is_tail_recursive(func) {
+        return None;
+    }
+    let sig = Signature::new(func)?;
+    let name = func.func_name().unwrap();
+    log::info!("Turning tail-recursive function {name} into a loop");
+
+    // the arguments of the current iteration, read from the loop tuple
+    let loop_state = sig.loop_state();
+    let loop_args = parallel_vec(
+        (0..sig.inputs.len())
+            .map(|i| {
+                let index = if i == sig.input_state {
+                    loop_state
+                } else {
+                    Signature::non_state_position(i, sig.input_state)
+                };
+                Rc::new(Expr::Get(arg(), index))
+            })
+            .collect::<Vec<_>>(),
+    );
+    let step = to_loop_step(func.func_body().unwrap(), &name, &sig);
+    let loop_body = subst_expr(&loop_args, &step);
+
+    let placeholders = |i: usize| placeholder(&sig.outputs[i]).unwrap();
+    let loop_inputs = parallel_vec(sig.loop_tuple(
+        |i| Rc::new(Expr::Get(arg(), i)),
+        placeholders,
+        Rc::new(Expr::Get(arg(), sig.input_state)),
+    ));
+    let the_loop = Rc::new(Expr::DoWhile(loop_inputs, loop_body));
+
+    let num_args = sig.inputs.len() - 1;
+    let outputs = (0..sig.outputs.len()).map(|i| {
+        let index = if i == sig.output_state {
+            loop_state
+        } else {
+            num_args + Signature::non_state_position(i, sig.output_state)
+        };
+        Rc::new(Expr::Get(the_loop.clone(), index))
+    });
+
+    Some(Rc::new(Expr::Function(
+        name,
+        func.func_input_ty().unwrap(),
+        func.func_output_ty().unwrap(),
+        parallel_vec(outputs.collect::<Vec<_>>()),
+    )))
+}
+
+/// Inlines calls to `callee` in the body of `func`.
+/// All calls are rewritten in one traversal, so nested calls and
+/// several calls in one branch are all inlined.
+fn inline_calls_to(func: &RcExpr, callee: &RcExpr) -> RcExpr {
+    fn go(
+        expr: &RcExpr,
+        callee_name: &str,
+        callee_body: &RcExpr,
+        cache: &mut IndexMap<*const Expr, RcExpr>,
+    ) -> RcExpr {
+        if let Some(res) = cache.get(&Rc::as_ptr(expr)) {
+            return res.clone();
+        }
+        let res = match expr.as_ref() {
+            Expr::Call(name, args) if name == callee_name => {
+                let args = go(args, callee_name, callee_body, cache);
+                subst_expr(&args, callee_body)
+            }
+            _ => expr.map_expr_children(|child| go(child, callee_name, callee_body, cache)),
+        };
+        cache.insert(Rc::as_ptr(expr), res.clone());
+        res
+    }
+    let callee_name = callee.func_name().unwrap();
+    let body = go(
+        func.func_body().unwrap(),
+        &callee_name,
+        callee.func_body().unwrap(),
+        &mut IndexMap::new(),
+    );
+    Rc::new(Expr::Function(
+        func.func_name().unwrap(),
+        func.func_input_ty().unwrap(),
+        func.func_output_ty().unwrap(),
+        body,
+    ))
+}
+
+/// Turns tail recursion into loops across the program.
+/// For two mutually recursive functions that don't call themselves,
+/// the callee is first inlined into the caller so that the caller calls itself.
+pub fn tail_recursion_to_loops(program: &TreeProgram) -> TreeProgram {
+    let mut res = program.clone();
+
+    let call_graph = CallGraph::new(program);
+    for scc in call_graph.sccs() {
+        let [first, second] = scc.as_slice() else {
+            continue;
+        };
+        if call_graph.calls(first, first) || call_graph.calls(second, second) {
+            continue;
+        }
+        // prefer making the entry (or the function called from outside) the loop
+        let entry_name = program.entry.func_name().unwrap();
+        let (caller, callee) = if *second == entry_name {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        let inlined = inline_calls_to(
+            program.get_function(caller).unwrap(),
+            program.get_function(callee).unwrap(),
+        );
+        if is_tail_recursive(&inlined) && Signature::new(&inlined).is_some() {
+            res.replace_fn(caller, inlined);
+        }
+    }
+
+    let mut changed = false;
+    for name in res.fns() {
+        if let Some(looped) = tail_recursion_to_loop(res.get_function(&name).unwrap()) {
+            res.replace_fn(&name, looped);
+            changed = true;
+        }
+    }
+    if changed {
+        res.override_arg_types()
+    } else {
+        res
+    }
+}
+
+#[test]
+fn test_tail_recursion_in_switch() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    // collatz steps, with the recursive calls in two branches of a switch
+    let prog = program!(
+        function(
+            "main",
+            tuplet!(intt(), statet()),
+            tuplet!(intt(), statet()),
+            call("steps", parallel!(getat(0), int(0), getat(1)))
+        ),
+        function(
+            "steps",
+            tuplet!(intt(), intt(), statet()),
+            tuplet!(intt(), statet()),
+            switch!(
+                select(
+                    less_eq(getat(0), int(1)),
+                    int(2),
+                    sub(getat(0), mul(div(getat(0), int(2)), int(2)))
+                ),
+                arg();
+                call(
+                    "steps",
+                    parallel!(div(getat(0), int(2)), add(getat(1), int(1)), getat(2))
+                ),
+                call(
+                    "steps",
+                    parallel!(
+                        add(mul(getat(0), int(3)), int(1)),
+                        add(getat(1), int(1)),
+                        getat(2)
+                    )
+                ),
+                parallel!(getat(1), getat(2))
+            )
+        ),
+    );
+    let looped = tail_recursion_to_loops(&prog);
+
+    let steps = looped.get_function("steps").unwrap();
+    assert!(calls_in_expr(steps.func_body().unwrap()).is_empty());
+    for n in [1, 6, 27] {
+        assert_eq!(
+            interpret_dag_prog(&prog, &tuplev!(intv(n), statev())),
+            interpret_dag_prog(&looped, &tuplev!(intv(n), statev()))
+        );
+    }
+}
+
+#[test]
+fn test_mutual_tail_recursion() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    let even_odd = |name: &str, other: &str, base: bool| {
+        function(
+            name,
+            tuplet!(intt(), statet()),
+            tuplet!(boolt(), statet()),
+            tif(
+                eq(getat(0), int(0)),
+                arg(),
+                parallel!(if base { ttrue() } else { tfalse() }, getat(1)),
+                call(other, parallel!(sub(getat(0), int(1)), getat(1))),
+            ),
+        )
+    };
+    let prog = program!(
+        function(
+            "main",
+            tuplet!(intt(), statet()),
+            tuplet!(boolt(), statet()),
+            call("even", arg())
+        ),
+        even_odd("even", "odd", true),
+        even_odd("odd", "even", false),
+    );
+    let looped = tail_recursion_to_loops(&prog);
+
+    let even = looped.get_function("even").unwrap();
+    assert!(calls_in_expr(even.func_body().unwrap()).is_empty());
+    for n in [0, 5, 10] {
+        assert_eq!(
+            interpret_dag_prog(&prog, &tuplev!(intv(n), statev())),
+            interpret_dag_prog(&looped, &tuplev!(intv(n), statev()))
+        );
+    }
+}
+
+#[test]
+fn test_inline_two_calls_in_one_branch() {
+    use crate::ast::*;
+    use crate::interpreter::interpret_dag_prog;
+    // f(n) = if n <= 0 {0} else {g(n - 1) + g(n - 2)}, with g calling f back
+    let prog = program!(
+        function(
+            "f",
+            tuplet!(intt(), statet()),
+            tuplet!(intt(), statet()),
+            tif(
+                less_eq(getat(0), int(0)),
+                arg(),
+                parallel!(int(0), getat(1)),
+                parallel!(
+                    add(
+                        get(call("g", parallel!(sub(getat(0), int(1)), getat(1))), 0),
+                        get(call("g", parallel!(sub(getat(0), int(2)), getat(1))), 0)
+                    ),
+                    getat(1)
+                ),
+            )
+        ),
+        function(
+            "g",
+            tuplet!(intt(), statet()),
+            tuplet!(intt(), statet()),
+            parallel!(
+                add(get(call("f", parallel!(getat(0), getat(1))), 0), int(1)),
+                getat(1)
+            )
+        ),
+    );
+    let inlined = inline_calls_to(
+        prog.get_function("f").unwrap(),
+        prog.get_function("g").unwrap(),
+    );
+
+    let calls = calls_in_expr(inlined.func_body().unwrap());
+    assert_eq!(calls.len(), 2);
+    assert!(calls
+        .iter()
+        .all(|call| matches!(call.as_ref(), Expr::Call(name, _) if name == "f")));
+    let mut res = prog.clone();
+    res.replace_fn("f", inlined);
+    for n in [0, 3, 6] {
+        assert_eq!(
+            interpret_dag_prog(&prog, &tuplev!(intv(n), statev())),
+            interpret_dag_prog(&res, &tuplev!(intv(n), statev()))
+        );
+    }
+}
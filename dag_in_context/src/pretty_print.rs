@@ -620,6 +620,7 @@ impl BinaryOp {
             Add => "add",
             Sub => "sub",
             Mul => "mul",
+            MulHi => "mulhi",
             Div => "div",
             Eq => "eq",
             LessThan => "less_than",
@@ -22,6 +22,27 @@ impl TreeProgram {
     }
 }
 
+/// The indices of the argument read by `body`.
+/// Nested regions have their own arguments, so only their inputs are considered.
+pub(crate) fn arg_indices_used(body: &RcExpr) -> HashSet<usize> {
+    let mut remover = DeadCodeRemover {
+        memo: HashMap::new(),
+        indices_used: HashMap::new(),
+    };
+    remover.indices_used(body.clone())
+}
+
+/// Removes the arguments at `dead_indices` from `body`,
+/// which must not read them (see `arg_indices_used`).
+/// Reads of the remaining arguments are shifted down to match.
+pub(crate) fn remove_dead_args(body: &RcExpr, dead_indices: &[usize]) -> RcExpr {
+    let mut remover = DeadCodeRemover {
+        memo: HashMap::new(),
+        indices_used: HashMap::new(),
+    };
+    remover.remove_dead_code_expr(body.clone(), &dead_indices.to_vec())
+}
+
 fn remove_dead_code_fn(func: RcExpr) -> RcExpr {
     match func.as_ref() {
         Expr::Function(name, ret_type, arg_type, body) => {
@@ -41,7 +62,7 @@ fn remove_dead_code_fn(func: RcExpr) -> RcExpr {
     }
 }
 
-fn remove_dead_code_ty(ty: Type, dead_indicies: &[usize]) -> Type {
+pub(crate) fn remove_dead_code_ty(ty: Type, dead_indicies: &[usize]) -> Type {
     match ty {
         Type::Base(base_type) => {
             assert!(dead_indicies.is_empty());
@@ -62,7 +83,7 @@ fn remove_dead_code_ty(ty: Type, dead_indicies: &[usize]) -> Type {
 }
 
 /// Try to split an expression that is a concat into multiple parts.
-fn try_split_inputs(expr: RcExpr) -> Option<Vec<RcExpr>> {
+pub(crate) fn try_split_inputs(expr: RcExpr) -> Option<Vec<RcExpr>> {
     match expr.as_ref() {
         Expr::Concat(left, right) => {
             let mut left_parts = try_split_inputs(left.clone())?;
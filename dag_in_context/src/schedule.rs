@@ -1,4 +1,4 @@
-use crate::EggccConfig;
+use crate::{config::POINTS_TO_ROUNDS, EggccConfig};
 
 #[derive(Debug)]
 pub enum CompilerPass {
@@ -86,7 +86,11 @@ pub(crate) fn helpers() -> String {
     ;; cicm index
     (saturate cicm-index)
 
-    ;; TODO right now we don't run memory-helpers, we run mem-simple instead
+    ;; points-to analysis, for a bounded number of rounds.
+    ;; When it doesn't reach a fixed point, the mem-simple facts above still apply.
+    (repeat {POINTS_TO_ROUNDS}
+        (saturate always-run memory-helpers)
+        memory-analysis)
 
     ;; finally, subsume now that helpers are done
     subsume-after-helpers
@@ -97,6 +101,7 @@ pub(crate) fn helpers() -> String {
     boundary-analysis
 
     loop-iters-analysis
+    (saturate loop-fission-analysis)
 "
     )
 }
@@ -106,7 +111,7 @@ fn cheap_optimizations() -> Vec<String> {
         "hacker",
         "interval-rewrite",
         "always-switch-rewrite",
-        // "memory", TODO right now we just run mem-simple
+        "memory",
         "peepholes",
     ]
     .iter()
@@ -117,11 +122,17 @@ fn cheap_optimizations() -> Vec<String> {
 fn optimizations() -> Vec<String> {
     [
         "select_opt",
+        "div-by-const",
         "loop-unroll",
+        "loop-peel",
+        "loop-fusion",
         "switch_rewrite",
         "loop-inv-motion",
         "loop-strength-reduction",
         "cicm",
+        "partial-redundancy",
+        "code-placement",
+        "branch-threading",
         "push-in",
     ]
     .iter()
@@ -146,7 +157,7 @@ pub fn rulesets() -> String {
     )
 }
 
-pub fn mk_sequential_schedule() -> Vec<CompilerPass> {
+pub fn mk_sequential_schedule(config: &EggccConfig) -> Vec<CompilerPass> {
     let helpers = helpers();
 
     let mut res = vec![CompilerPass::Schedule(format!(
@@ -191,11 +202,24 @@ pub fn mk_sequential_schedule() -> Vec<CompilerPass> {
 "
         ))
     }));
+    // float rewrites that don't preserve rounding are opt-in
+    if config.fast_math {
+        res.push(CompilerPass::Schedule(format!(
+            "
+(run-schedule
+   {helpers}
+   fast-math
+   {helpers})
+"
+        )));
+    }
     res
 }
 
 pub fn parallel_schedule(config: &EggccConfig) -> Vec<CompilerPass> {
     let helpers = helpers();
+    // float rewrites that don't preserve rounding are opt-in
+    let fast_math = if config.fast_math { "fast-math" } else { "" };
 
     vec![
         CompilerPass::Schedule(format!(
@@ -232,6 +256,7 @@ pub fn parallel_schedule(config: &EggccConfig) -> Vec<CompilerPass> {
     (repeat 2
         {helpers}
         all-optimizations
+        {fast_math}
     )
     ;; non-weakly-linear optimizations once
         {}
@@ -239,6 +264,7 @@ pub fn parallel_schedule(config: &EggccConfig) -> Vec<CompilerPass> {
     (repeat 4
         {helpers}
         cheap-optimizations
+        {fast_math}
     )
 
     (repeat 3
@@ -42,6 +42,7 @@ pub enum BinaryOp {
     Add,
     Sub,
     Mul,
+    MulHi,
     Div,
     Eq,
     LessThan,
@@ -67,6 +67,7 @@ impl BinaryOp {
             Add => "Add",
             Sub => "Sub",
             Mul => "Mul",
+            MulHi => "MulHi",
             Div => "Div",
             Eq => "Eq",
             GreaterThan => "GreaterThan",
@@ -833,6 +834,7 @@ impl BinaryOp {
             | BinaryOp::Add
             | BinaryOp::Sub
             | BinaryOp::Mul
+            | BinaryOp::MulHi
             | BinaryOp::Div
             | BinaryOp::Smax
             | BinaryOp::Smin
@@ -45,6 +45,16 @@ impl TreeProgram {
         checker.add_arg_types()
     }
 
+    /// Like override_arg_types, but for an expression in this program
+    /// whose enclosing region has argument type `arg_ty`.
+    pub(crate) fn override_expr_arg_types(&self, expr: &RcExpr, arg_ty: Type) -> RcExpr {
+        let mut checker = TypeChecker::new(self, false);
+        checker.allow_garbage_arg_types = true;
+        let (_ty, new_expr) =
+            checker.add_arg_types_to_expr(expr.clone(), &Some(TypeStack(vec![arg_ty])));
+        new_expr
+    }
+
     pub fn with_arg_types_and_cache(&self) -> (TreeProgram, TypeCache) {
         let mut checker = TypeChecker::new(self, false);
         let prog = checker.add_arg_types();
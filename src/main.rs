@@ -1,5 +1,5 @@
 use clap::Parser;
-use dag_in_context::{EggccConfig, IlpSolver, Schedule};
+use dag_in_context::{EggccConfig, IlpSolver, InlinePolicy, Schedule};
 use eggcc::util::{visualize, InterpMode, LLVMOptLevel, Run, RunMode, TestProgram};
 use std::{ffi::OsStr, iter::once, path::PathBuf};
 
@@ -100,6 +100,22 @@ struct Args {
     /// Choose which ILP solver to use when running the tiger extractor.
     #[clap(long, value_enum, default_value_t = IlpSolver::Gurobi)]
     ilp_solver: IlpSolver,
+    /// How many rounds of function inlining to perform (0 disables inlining).
+    #[clap(long)]
+    inline_iterations: Option<usize>,
+    /// Inline callees whose tree size is at most this.
+    /// Recursive functions are never inlined.
+    #[clap(long)]
+    inline_max_size: Option<usize>,
+    /// Don't clone functions to specialize them to constant arguments.
+    #[clap(long)]
+    no_specialize: bool,
+    /// Don't offer nested loops in interchanged order to the extractor.
+    #[clap(long)]
+    no_loop_interchange: bool,
+    /// Allow float rewrites that change rounding, like reassociation.
+    #[clap(long)]
+    fast_math: bool,
 }
 
 fn main() {
@@ -110,6 +126,17 @@ fn main() {
 
     let start_time = std::time::Instant::now();
 
+    let default_inline_policy = InlinePolicy::default();
+    let inline_policy = InlinePolicy {
+        iterations: args
+            .inline_iterations
+            .unwrap_or(default_inline_policy.iterations),
+        max_callee_size: args
+            .inline_max_size
+            .unwrap_or(default_inline_policy.max_callee_size),
+        ..default_inline_policy
+    };
+
     if let Some(debug_dir) = args.debug_dir {
         if let Result::Err(error) = visualize(TestProgram::BrilFile(args.file.clone()), debug_dir) {
             eprintln!("{}", error);
@@ -161,6 +188,10 @@ fn main() {
             ilp_minimize_objective: !args.ilp_no_minimize,
             ilp_solver: args.ilp_solver,
             egraph_dump_dir: args.egraph_out_dir,
+            inline_policy,
+            specialize_functions: !args.no_specialize,
+            interchange_loops: !args.no_loop_interchange,
+            fast_math: args.fast_math,
         },
     };
 
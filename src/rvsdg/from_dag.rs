@@ -6,10 +6,10 @@ use std::rc::Rc;
 
 use bril_rs::{ConstOps, EffectOps, Literal, ValueOps};
 use dag_in_context::{
-    schema::{BaseType, BinaryOp, Expr, RcExpr, TernaryOp, TreeProgram, Type, UnaryOp},
+    schema::{BaseType, BinaryOp, Constant, Expr, RcExpr, TernaryOp, TreeProgram, Type, UnaryOp},
     typechecker::TypeCache,
 };
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 
 use super::{BasicExpr, Operand, RvsdgBody, RvsdgFunction, RvsdgProgram, RvsdgType};
 
@@ -31,6 +31,12 @@ struct TreeToRvsdg<'a> {
     current_args: Vec<Operand>,
 }
 
+/// The Bril function that multiply-highs are lowered to.
+/// Bril has no multiply-high, so its body computes one from 32-bit halves.
+/// The LLVM backend rebuilds the body as a widening multiply
+/// (see `native_mul_hi` in `util.rs`).
+pub(crate) const MUL_HI_FUNCTION: &str = "__eggcc_mul_hi";
+
 pub(crate) fn dag_to_rvsdg(tree: &TreeProgram) -> RvsdgProgram {
     let mut res = RvsdgProgram { functions: vec![] };
     for func in &tree.functions {
@@ -38,9 +44,53 @@ pub(crate) fn dag_to_rvsdg(tree: &TreeProgram) -> RvsdgProgram {
     }
     res.functions
         .push(tree_func_to_rvsdg(tree.entry.clone(), tree));
+    // a program that went through eggcc before already has the function
+    if uses_mul_hi(tree) && tree.get_function(MUL_HI_FUNCTION).is_none() {
+        res.functions.push(mul_hi_function(tree));
+    }
     res
 }
 
+fn uses_mul_hi(tree: &TreeProgram) -> bool {
+    let mut seen = IndexSet::new();
+    let mut todo = tree
+        .fns()
+        .iter()
+        .map(|name| tree.get_function(name).unwrap().clone())
+        .collect::<Vec<_>>();
+    while let Some(expr) = todo.pop() {
+        if !seen.insert(Rc::as_ptr(&expr)) {
+            continue;
+        }
+        if matches!(expr.as_ref(), Expr::Bop(BinaryOp::MulHi, _, _)) {
+            return true;
+        }
+        todo.extend(expr.children_exprs());
+    }
+    false
+}
+
+/// The pure function `MUL_HI_FUNCTION`, taking two ints.
+fn mul_hi_function(tree: &TreeProgram) -> RvsdgFunction {
+    let mut nodes = vec![];
+    let type_cache = TypeCache::new();
+    let mut converter = TreeToRvsdg {
+        program: tree,
+        type_cache: &type_cache,
+        translation_cache: IndexMap::new(),
+        nodes: &mut nodes,
+        current_args: vec![Operand::Arg(0), Operand::Arg(1)],
+    };
+    let res = converter.convert_mul_hi(Operand::Arg(0), Operand::Arg(1));
+    let int = RvsdgType::Bril(bril_rs::Type::Int);
+    RvsdgFunction {
+        name: MUL_HI_FUNCTION.to_string(),
+        args: vec![int.clone(), int.clone()],
+        nodes,
+        results: vec![(int, res[0])],
+    }
+}
+
 fn basetype_to_bril_type(ty: BaseType) -> bril_rs::Type {
     match ty {
         BaseType::IntT => bril_rs::Type::Int,
@@ -152,6 +202,8 @@ fn value_op_from_binary_op(bop: BinaryOp) -> Option<ValueOps> {
         BinaryOp::Add => Some(ValueOps::Add),
         BinaryOp::Sub => Some(ValueOps::Sub),
         BinaryOp::Mul => Some(ValueOps::Mul),
+        // lowered to a call to MUL_HI_FUNCTION
+        BinaryOp::MulHi => None,
         BinaryOp::Div => Some(ValueOps::Div),
         BinaryOp::Eq => Some(ValueOps::Eq),
         BinaryOp::LessThan => Some(ValueOps::Lt),
@@ -238,6 +290,75 @@ impl<'a> TreeToRvsdg<'a> {
             .collect()
     }
 
+    /// The interpreter shifts by the amount modulo 64, like Rust's `wrapping_shl`,
+    /// but shifting by 64 or more is undefined in LLVM, so we mask the amount
+    /// unless it is a constant that is already in range.
+    fn convert_shift(
+        &mut self,
+        vop: ValueOps,
+        value: Operand,
+        amount: Operand,
+        in_range: bool,
+    ) -> Operands {
+        let int = bril_rs::Type::Int;
+        let amount = if in_range {
+            amount
+        } else {
+            let mask = self.push_basic(BasicExpr::Const(
+                ConstOps::Const,
+                Literal::Int(63),
+                int.clone(),
+            ))[0];
+            self.push_basic(BasicExpr::Op(
+                ValueOps::Bitand,
+                vec![amount, mask],
+                int.clone(),
+            ))[0]
+        };
+        self.push_basic(BasicExpr::Op(vop, vec![value, amount], int))
+    }
+
+    /// The body of `MUL_HI_FUNCTION`, which computes a multiply-high
+    /// from the 32-bit halves of the operands (Hacker's Delight, figure 8-2).
+    /// Shifts are arithmetic, so the low half of a product is masked after shifting.
+    fn convert_mul_hi(&mut self, a: Operand, b: Operand) -> Operands {
+        let int = bril_rs::Type::Int;
+        let op = |this: &mut Self, vop: ValueOps, l: Operand, r: Operand| {
+            this.push_basic(BasicExpr::Op(vop, vec![l, r], int.clone()))[0]
+        };
+        let konst = |this: &mut Self, n: i64| {
+            this.push_basic(BasicExpr::Const(
+                ConstOps::Const,
+                Literal::Int(n),
+                int.clone(),
+            ))[0]
+        };
+        let mask = konst(self, 0xFFFF_FFFF);
+        let shift = konst(self, 32);
+
+        let a0 = op(self, ValueOps::Bitand, a, mask);
+        let a1 = op(self, ValueOps::Shr, a, shift);
+        let b0 = op(self, ValueOps::Bitand, b, mask);
+        let b1 = op(self, ValueOps::Shr, b, shift);
+
+        // w0 = a0 * b0, t = a1 * b0 + (w0 >>> 32)
+        let w0 = op(self, ValueOps::Mul, a0, b0);
+        let w0_hi = op(self, ValueOps::Shr, w0, shift);
+        let w0_hi = op(self, ValueOps::Bitand, w0_hi, mask);
+        let t = op(self, ValueOps::Mul, a1, b0);
+        let t = op(self, ValueOps::Add, t, w0_hi);
+        // w1 = a0 * b1 + (t & mask), w2 = t >> 32
+        let w1 = op(self, ValueOps::Bitand, t, mask);
+        let w2 = op(self, ValueOps::Shr, t, shift);
+        let a0b1 = op(self, ValueOps::Mul, a0, b1);
+        let w1 = op(self, ValueOps::Add, a0b1, w1);
+        // a1 * b1 + w2 + (w1 >> 32)
+        let hi = op(self, ValueOps::Mul, a1, b1);
+        let hi = op(self, ValueOps::Add, hi, w2);
+        let w1_hi = op(self, ValueOps::Shr, w1, shift);
+        vec![op(self, ValueOps::Add, hi, w1_hi)]
+    }
+
     /// Some expressions such as Load and Alloc also return a state edge,
     /// so we need to ignore this when computing the bril type.
     fn get_basic_expr_type(&self, expr: RcExpr) -> bril_rs::Type {
@@ -305,13 +426,28 @@ impl<'a> TreeToRvsdg<'a> {
                 ))
             }
             Expr::Bop(op, l, r) => {
+                let shift_in_range = matches!(r.as_ref(), Expr::Const(Constant::Int(0..=63), _, _));
                 let l = self.convert_expr(l.clone());
                 let r = self.convert_expr(r.clone());
                 assert_eq!(l.len(), 1, "Expected exactly one result for left operand");
                 assert_eq!(r.len(), 1, "Expected exactly one result for right operand");
                 let l = l[0];
                 let r = r[0];
-                if let Some(vop) = value_op_from_binary_op(op.clone()) {
+                if *op == BinaryOp::MulHi {
+                    self.push_basic(BasicExpr::Call(
+                        MUL_HI_FUNCTION.to_string(),
+                        vec![l, r],
+                        1,
+                        Some(bril_rs::Type::Int),
+                    ))
+                } else if matches!(op, BinaryOp::Shl | BinaryOp::Shr) {
+                    self.convert_shift(
+                        value_op_from_binary_op(op.clone()).unwrap(),
+                        l,
+                        r,
+                        shift_in_range,
+                    )
+                } else if let Some(vop) = value_op_from_binary_op(op.clone()) {
                     let bril_type = self.get_basic_expr_type(expr.clone());
                     self.push_basic(BasicExpr::Op(vop, vec![l, r], bril_type))
                 } else if let Some(eop) = effect_op_from_binary_op(op.clone()) {
@@ -472,3 +608,64 @@ impl<'a> TreeToRvsdg<'a> {
         res
     }
 }
+
+#[test]
+fn shift_amounts_are_masked() {
+    use bril_rs::{Code, Instruction};
+    use dag_in_context::ast::*;
+    // (x << y) + (x >> 3): only the variable amount needs a mask
+    let prog = program!(function(
+        "main",
+        tuplet!(intt(), intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(
+            add(shl(getat(0), getat(1)), shr(getat(0), int(3))),
+            getat(2)
+        ),
+    ),);
+    let bril = dag_to_rvsdg(&prog).to_cfg().to_bril();
+    let masks = bril.functions[0]
+        .instrs
+        .iter()
+        .filter(|code| {
+            matches!(
+                code,
+                Code::Instruction(Instruction::Value {
+                    op: ValueOps::Bitand,
+                    ..
+                })
+            )
+        })
+        .count();
+    assert_eq!(masks, 1);
+}
+
+#[test]
+fn mul_hi_is_a_call() {
+    use bril_rs::{Code, Instruction};
+    use dag_in_context::ast::*;
+    let prog = program!(function(
+        "main",
+        tuplet!(intt(), statet()),
+        tuplet!(intt(), statet()),
+        parallel!(mulhi(int(5270498306774157605), getat(0)), getat(1)),
+    ),);
+    let bril = dag_to_rvsdg(&prog).to_cfg().to_bril();
+    let calls = bril.functions[0]
+        .instrs
+        .iter()
+        .filter(|code| {
+            matches!(
+                code,
+                Code::Instruction(Instruction::Value {
+                    op: ValueOps::Call,
+                    funcs,
+                    ..
+                }) if funcs == &[MUL_HI_FUNCTION.to_string()]
+            )
+        })
+        .count();
+    assert_eq!(calls, 1);
+    assert_eq!(bril.functions.len(), 2);
+    assert_eq!(bril.functions[1].name, MUL_HI_FUNCTION);
+}
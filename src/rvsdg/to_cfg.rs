@@ -380,6 +380,61 @@ impl<'a> RvsdgToCfg<'a> {
         }
     }
 
+    /// Jump from `start` to the branch blocks of a switch on the integer `pred`.
+    /// Bril has no jump tables, so each case but the last is tested in turn,
+    /// and the last branch is taken when every test fails.
+    fn add_switch_tests(
+        &mut self,
+        start: NodeIndex,
+        pred: &str,
+        branch_blocks: &[TranslationResult],
+    ) {
+        let (last, cases) = branch_blocks.split_last().expect("switch with no branches");
+        // the block that runs the next test, and the test that must fail to get there
+        let mut current = start;
+        let mut failed_test: Option<String> = None;
+        for (i, case) in cases.iter().enumerate() {
+            let case_num = self.get_fresh();
+            let is_case = self.get_fresh();
+            let test = self.make_block(vec![
+                Instruction::Constant {
+                    dest: case_num.clone(),
+                    op: ConstOps::Const,
+                    value: Literal::Int(i as i64),
+                    pos: None,
+                    const_type: Type::Int,
+                },
+                Instruction::Value {
+                    dest: is_case.clone(),
+                    op: ValueOps::Eq,
+                    args: vec![pred.to_string(), case_num],
+                    funcs: vec![],
+                    labels: vec![],
+                    pos: None,
+                    op_type: Type::Bool,
+                },
+            ]);
+            self.graph
+                .add_edge(current, test, switch_fallthrough(&failed_test));
+            self.graph.add_edge(
+                test,
+                case.start,
+                Branch {
+                    op: BranchOp::Cond {
+                        arg: Identifier::Name(is_case.clone()),
+                        val: true.into(),
+                        bril_type: Type::Bool,
+                    },
+                    pos: None,
+                },
+            );
+            current = test;
+            failed_test = Some(is_case);
+        }
+        self.graph
+            .add_edge(current, last.start, switch_fallthrough(&failed_test));
+    }
+
     /// Find a branch of a Gamma that simply passes through the inputs to the outputs,
     /// possibly permuting them.
     fn find_passthrough_case(&self, outputs: &[Vec<Operand>]) -> Option<usize> {
@@ -433,9 +488,23 @@ impl<'a> RvsdgToCfg<'a> {
             } => {
                 let pred = self.operand_to_bril(*pred, current_args, ctx);
                 // convert the predicate to a bool, since this might
-                // actually be an `If`
-                let pred_bool = self.cast_bool(&pred.get_single_res());
-                let pred_res = self.sequence_results(&[pred, pred_bool.clone()]);
+                // actually be an `If`.
+                // Switches with more branches compare the integer predicate
+                // against each case instead.
+                let (pred_res, pred_bool) = if outputs.len() == 2 {
+                    let pred_bool = self.cast_bool(&pred.get_single_res());
+                    (
+                        self.sequence_results(&[pred, pred_bool.clone()]),
+                        Some(pred_bool),
+                    )
+                } else {
+                    (pred, None)
+                };
+                let pred_name = pred_bool
+                    .as_ref()
+                    .unwrap_or(&pred_res)
+                    .get_single_res()
+                    .unwrap_name();
 
                 let input_vars = inputs
                     .iter()
@@ -497,36 +566,25 @@ impl<'a> RvsdgToCfg<'a> {
 
                 // we need to conditionally jump to each of the branch blocks
                 // based on the predicate
-                // TODO right now we
-                // just handle the case where we branch to two things, and use phi nodes to join back up
-                assert_eq!(outputs.len(), 2);
-                assert_eq!(branch_blocks.len(), 2);
-
-                // add a conditional jump from the previous block to the branch blocks
-                self.graph.add_edge(
-                    before_if.end,
-                    branch_blocks[0].start,
-                    Branch {
-                        op: BranchOp::Cond {
-                            arg: Identifier::Name(pred_bool.get_single_res().unwrap_name()),
-                            val: false.into(),
-                            bril_type: Type::Bool,
-                        },
-                        pos: None,
-                    },
-                );
-                self.graph.add_edge(
-                    before_if.end,
-                    branch_blocks[1].start,
-                    Branch {
-                        op: BranchOp::Cond {
-                            arg: Identifier::Name(pred_bool.get_single_res().unwrap_name()),
-                            val: true.into(),
-                            bril_type: Type::Bool,
-                        },
-                        pos: None,
-                    },
-                );
+                if pred_bool.is_some() {
+                    assert_eq!(branch_blocks.len(), 2);
+                    for (branch_block, val) in branch_blocks.iter().zip([false, true]) {
+                        self.graph.add_edge(
+                            before_if.end,
+                            branch_block.start,
+                            Branch {
+                                op: BranchOp::Cond {
+                                    arg: Identifier::Name(pred_name.clone()),
+                                    val: val.into(),
+                                    bril_type: Type::Bool,
+                                },
+                                pos: None,
+                            },
+                        );
+                    }
+                } else {
+                    self.add_switch_tests(before_if.end, &pred_name, &branch_blocks);
+                }
 
                 // now make a block at the end
                 let end_block = self.sequence_results(&[]);
@@ -862,3 +920,17 @@ impl<'a> RvsdgToCfg<'a> {
         }
     }
 }
+
+/// The edge taken when the previous switch test fails,
+/// or a plain jump before the first test.
+fn switch_fallthrough(failed_test: &Option<String>) -> Branch {
+    let op = match failed_test {
+        Some(test) => BranchOp::Cond {
+            arg: Identifier::Name(test.clone()),
+            val: false.into(),
+            bril_type: Type::Bool,
+        },
+        None => BranchOp::Jmp,
+    };
+    Branch { op, pos: None }
+}
@@ -1,5 +1,5 @@
 use crate::canonicalize_names::canonicalize_bril;
-use crate::rvsdg::from_dag::dag_to_rvsdg;
+use crate::rvsdg::from_dag::{dag_to_rvsdg, MUL_HI_FUNCTION};
 use crate::{EggCCError, Optimizer};
 use bril_rs::Program;
 use clap::ValueEnum;
@@ -11,6 +11,9 @@ use dag_in_context::{
 };
 
 use dag_in_context::schema::TreeProgram;
+use inkwell::attributes::{Attribute, AttributeLoc};
+use inkwell::context::Context;
+use inkwell::memory_buffer::MemoryBuffer;
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 use std::fs::File;
@@ -425,6 +428,9 @@ pub struct RunResult {
     pub eggcc_serialization_time: Duration,
     /// Per-region timings collected from the tiger extractor.
     pub extract_region_timings: Vec<ExtractRegionTiming>,
+    /// Whether float rewrites that change rounding were enabled.
+    #[serde(default)]
+    pub fast_math: bool,
 }
 
 impl Run {
@@ -762,6 +768,8 @@ impl Run {
                     "",
                     self.eggcc_config.ablate.as_deref(),
                     self.eggcc_config.use_context,
+                    false,
+                    &self.eggcc_config.inline_policy,
                 );
                 let folded_program = tree.pretty_print_to_egglog();
                 let program =
@@ -848,6 +856,8 @@ impl Run {
                     last_schedule_step.egglog_schedule(),
                     eggcc_config.ablate.as_deref(),
                     eggcc_config.use_context,
+                    eggcc_config.interchange_loops,
+                    &eggcc_config.inline_policy,
                 );
                 (
                     vec![Visualization {
@@ -1007,6 +1017,7 @@ impl Run {
             eggcc_extraction_time: time_statistics.eggcc_extraction_time,
             eggcc_serialization_time: time_statistics.eggcc_serialization_time,
             extract_region_timings: time_statistics.extract_region_timings,
+            fast_math: self.eggcc_config.fast_math,
         })
     }
 
@@ -1107,6 +1118,8 @@ impl Run {
             add_timing,
         });
 
+        let llvm_ir = native_mul_hi(&llvm_ir);
+
         let init_ll_name = format!("{}-init.ll", self.name());
         let file_path = dir.path().join(init_ll_name.clone());
         let mut file = File::create(file_path.clone()).expect("couldn't create temp file");
@@ -1240,6 +1253,47 @@ impl Run {
     }
 }
 
+/// Replaces the body of `MUL_HI_FUNCTION` with a widening multiply,
+/// built with the LLVM API on the module brillvm produced.
+/// LLVM inlines it and lowers the multiply to a single multiply-high instruction,
+/// which Bril can't express.
+fn native_mul_hi(llvm_ir: &str) -> String {
+    let context = Context::create();
+    let buffer = MemoryBuffer::create_from_memory_range_copy(llvm_ir.as_bytes(), "program");
+    let module = context
+        .create_module_from_ir(buffer)
+        .unwrap_or_else(|err| panic!("brillvm produced invalid LLVM IR: {err}"));
+    let Some(func) = module.get_function(MUL_HI_FUNCTION) else {
+        return llvm_ir.to_string();
+    };
+    for block in func.get_basic_blocks() {
+        unsafe { block.delete() }.expect("failed to delete block");
+    }
+
+    let builder = context.create_builder();
+    builder.position_at_end(context.append_basic_block(func, "entry"));
+    let (i64_type, i128_type) = (context.i64_type(), context.i128_type());
+    let widen = |index: u32, name: &str| {
+        let arg = func.get_nth_param(index).unwrap().into_int_value();
+        builder.build_int_s_extend(arg, i128_type, name).unwrap()
+    };
+    let (wide_a, wide_b) = (widen(0, "wide_a"), widen(1, "wide_b"));
+    let product = builder.build_int_mul(wide_a, wide_b, "product").unwrap();
+    let shift = i128_type.const_int(64, false);
+    let high = builder
+        .build_right_shift(product, shift, true, "high")
+        .unwrap();
+    let res = builder.build_int_truncate(high, i64_type, "res").unwrap();
+    builder.build_return(Some(&res)).unwrap();
+
+    let always_inline = Attribute::get_named_enum_kind_id("alwaysinline");
+    func.add_attribute(
+        AttributeLoc::Function,
+        context.create_enum_attribute(always_inline, 0),
+    );
+    module.print_to_string().to_string()
+}
+
 fn expect_command_success(cmd: &mut std::process::Command, message: &str) -> String {
     let output = cmd.output().unwrap();
     if !output.status.success() {
@@ -1279,7 +1333,26 @@ impl FreshNameGen {
 mod test {
     use dag_in_context::{EggccConfig, Schedule};
 
-    use super::{Run, RunMode};
+    use super::{native_mul_hi, Run, RunMode};
+
+    #[test]
+    fn test_native_mul_hi() {
+        let llvm_ir = "declare void @_bril_print_int(i64)
+define i64 @__eggcc_mul_hi(i64 %0, i64 %1) {
+entry:
+  %2 = and i64 %0, 4294967295
+  ret i64 %2
+}
+define void @_main() {
+  ret void
+}
+";
+        let replaced = native_mul_hi(llvm_ir);
+        assert!(replaced.contains("mul i128"));
+        assert!(replaced.contains("alwaysinline"));
+        assert!(!replaced.contains("and i64"));
+        assert!(replaced.contains("define void @_main()"));
+    }
 
     #[test]
     fn test_to_egglog_cutoff() {